@@ -2,14 +2,18 @@ use dotenvy::dotenv;
 use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use std::collections::HashMap;
 use std::env;
+use std::io::Read;
 use strfmt::strfmt;
 
 const DEFAULT_AIM_PROGRESSBAR_DOWNLOADED_MESSAGE: &str = "🎯 Downloaded {input} to {output}";
 const DEFAULT_AIM_PROGRESSBAR_MESSAGE_FORMAT: &str = "🎯 Transferring {url}";
 const DEFAULT_AIM_PROGRESSBAR_PROGRESS_CHARS: &str = "█▉▊▋▌▍▎▏  ";
 const DEFAULT_AIM_PROGRESSBAR_TEMPLATE: &str = "{msg}\n{spinner:.cyan}  {elapsed_precise} ▕{bar:.white}▏ {bytes}/{total_bytes}  {bytes_per_sec}  ETA {eta}.";
+const DEFAULT_AIM_PROGRESSBAR_SPINNER_TEMPLATE: &str =
+    "{msg}\n{spinner:.cyan}  {elapsed_precise}  {bytes}  {bytes_per_sec}.";
 const DEFAULT_AIM_PROGRESSBAR_UPLOADED_MESSAGE: &str = "🎯 Uploaded {input} to {output}";
 
+const SPINNER_TICK_MILLISECONDS: u64 = 100;
 const THRESHOLD_IF_TOTALBYTES_BELOW_THEN_AUTO_SILENT_MODE: u64 = 1024 * 1024;
 
 fn construct_progress_bar(
@@ -42,6 +46,7 @@ pub struct WrappedBar {
     pub output: Option<indicatif::ProgressBar>,
     downloaded_message: String,
     uploaded_message: String,
+    spinner_template: String,
 }
 
 impl WrappedBar {
@@ -51,6 +56,7 @@ impl WrappedBar {
             output: None,
             downloaded_message: "".to_string(),
             uploaded_message: "".to_string(),
+            spinner_template: "".to_string(),
         }
     }
     pub fn new_empty_verbose() -> Self {
@@ -59,6 +65,7 @@ impl WrappedBar {
             output: None,
             downloaded_message: "".to_string(),
             uploaded_message: "".to_string(),
+            spinner_template: "".to_string(),
         }
     }
     pub fn new(total_size: u64, url: &str, silent: bool) -> Self {
@@ -73,6 +80,8 @@ impl WrappedBar {
             .unwrap_or_else(|_| DEFAULT_AIM_PROGRESSBAR_DOWNLOADED_MESSAGE.to_string());
         let uploaded_message = &env::var("AIM_PROGRESSBAR_UPLOADED_MESSAGE")
             .unwrap_or_else(|_| DEFAULT_AIM_PROGRESSBAR_UPLOADED_MESSAGE.to_string());
+        let spinner_template = &env::var("AIM_PROGRESSBAR_SPINNER_TEMPLATE")
+            .unwrap_or_else(|_| DEFAULT_AIM_PROGRESSBAR_SPINNER_TEMPLATE.to_string());
         let output = match silent {
             false => Some(construct_progress_bar(
                 total_size,
@@ -88,6 +97,7 @@ impl WrappedBar {
             output,
             downloaded_message: downloaded_message.to_string(),
             uploaded_message: uploaded_message.to_string(),
+            spinner_template: spinner_template.to_string(),
         }
     }
 
@@ -104,6 +114,32 @@ impl WrappedBar {
         }
     }
 
+    pub fn set_size(&mut self, size: Option<u64>) {
+        match size {
+            Some(len) => self.set_length(len),
+            None => self.set_spinner(),
+        }
+    }
+
+    pub fn set_spinner(&mut self) {
+        if let Some(output) = self.output.as_ref().filter(|_| !self.silent) {
+            output.set_style(
+                ProgressStyle::default_spinner()
+                    .template(&self.spinner_template)
+                    .unwrap(),
+            );
+            output.set_draw_target(ProgressDrawTarget::stderr());
+            output.enable_steady_tick(std::time::Duration::from_millis(SPINNER_TICK_MILLISECONDS));
+        }
+    }
+
+    pub fn wrap_read<'a, R: Read + 'a>(&self, reader: R) -> Box<dyn Read + 'a> {
+        match self.output.as_ref() {
+            Some(output) => Box::new(output.wrap_read(reader)),
+            None => Box::new(reader),
+        }
+    }
+
     pub fn set_position(&self, pos: u64) {
         if !self.silent {
             self.output.as_ref().unwrap().set_position(pos);
@@ -163,3 +199,23 @@ fn test_bar_set_length_works_when_typical() {
     let mut bar = WrappedBar::new(42, "url", false);
     bar.set_length(42);
 }
+
+#[test]
+fn test_bar_set_size_works_when_unknown() {
+    let mut bar = WrappedBar::new(0, "url", false);
+    bar.set_size(None);
+    bar.set_position(42);
+    bar.finish_upload("", "");
+}
+
+#[test]
+fn test_bar_wrap_read_works_when_silent() {
+    let bar = WrappedBar::new(42, "url", true);
+    let mut actual = String::new();
+
+    bar.wrap_read("content".as_bytes())
+        .read_to_string(&mut actual)
+        .unwrap();
+
+    assert_eq!(actual, "content");
+}
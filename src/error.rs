@@ -1,9 +1,32 @@
 extern crate custom_error;
 use custom_error::custom_error;
 
+custom_error! {
+    pub TimeoutError
+    Connect = "Connection timed out.",
+    Read = "Read timed out.",
+    Total = "Maximum transfer time exceeded.",
+    LowSpeed{limit: u64, time: u64} = "Transfer slower than {limit} bytes/sec for {time} seconds.",
+}
+
+custom_error! {
+    pub HostKeyError
+    Mismatch{host: String, fingerprint: String} = "Host key for {host} does not match known_hosts, got {fingerprint}. Someone may be intercepting the connection.",
+    Unknown{host: String} = "Host {host} is not in known_hosts. Use --host-key-checking accept-new to add it.",
+    Rejected{host: String} = "Host key for {host} was not accepted.",
+    Unreadable{reason: String} = "Cannot verify the host key: {reason}",
+}
+
 custom_error! {
     pub ValidateError
     Sha256Mismatch = "Invalid sha256.",
+    Timeout{source: TimeoutError} = "{source}",
+    HostKey{source: HostKeyError} = "{source}",
+    Unauthorized{host: String} = "Authentication failed for {host}.",
+    HttpStatus{url: String, status: u16} = "{url} returned HTTP {status}.",
+    Unsupported{reason: String} = "{reason}",
+    Response{url: String, reason: String} = "Cannot read the response from {url}: {reason}",
+    Proxy{server: String, reason: String} = "Cannot reach {server} through the ssh proxy: {reason}",
 }
 
 custom_error! {
@@ -13,7 +36,21 @@ custom_error! {
 
 impl From<ValidateError> for std::io::Error {
     fn from(cause: ValidateError) -> std::io::Error {
-        std::io::Error::other(cause.to_string())
+        match cause {
+            ValidateError::Timeout { .. } => {
+                std::io::Error::new(std::io::ErrorKind::TimedOut, cause)
+            }
+            ValidateError::HostKey { .. } | ValidateError::Unauthorized { .. } => {
+                std::io::Error::new(std::io::ErrorKind::PermissionDenied, cause)
+            }
+            _ => std::io::Error::other(cause.to_string()),
+        }
+    }
+}
+
+impl From<TimeoutError> for std::io::Error {
+    fn from(cause: TimeoutError) -> std::io::Error {
+        ValidateError::from(cause).into()
     }
 }
 
@@ -27,3 +64,9 @@ fn test_from_validate_error_to_std_io_error_works_when_typical() {
         }
     };
 }
+
+#[test]
+fn test_from_timeout_error_to_std_io_error_is_timed_out() {
+    let error: std::io::Error = ValidateError::from(TimeoutError::Read).into();
+    assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+}
@@ -0,0 +1,435 @@
+use glob::Pattern;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::thread::JoinHandle;
+
+use crate::driver::Options;
+use crate::io::{channel, ChannelWriter, Compression};
+use crate::slicer::Slicer;
+
+const CHANNEL_CAPACITY: usize = 64;
+
+static EXTRACTIONS: LazyLock<Mutex<HashMap<String, JoinHandle<io::Result<()>>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, PartialEq)]
+pub enum Archive {
+    Tar(Option<Compression>),
+    Zip,
+    Single(Compression),
+}
+
+impl Archive {
+    const EXTENSIONS: [(&'static str, Archive); 9] = [
+        (".tar", Archive::Tar(None)),
+        (".tar.gz", Archive::Tar(Some(Compression::Gzip))),
+        (".tgz", Archive::Tar(Some(Compression::Gzip))),
+        (".tar.xz", Archive::Tar(Some(Compression::Xz))),
+        (".txz", Archive::Tar(Some(Compression::Xz))),
+        (".tar.zst", Archive::Tar(Some(Compression::Zstd))),
+        (".tar.zstd", Archive::Tar(Some(Compression::Zstd))),
+        (".tzst", Archive::Tar(Some(Compression::Zstd))),
+        (".zip", Archive::Zip),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Archive> {
+        let lowercase = name.to_lowercase();
+        Archive::EXTENSIONS
+            .into_iter()
+            .find(|(extension, _)| lowercase.ends_with(extension))
+            .map(|(_, archive)| archive)
+            .or_else(|| Compression::from_name(name).map(Archive::Single))
+    }
+
+    fn decoder<'r, R: Read + 'r>(
+        compression: &Compression,
+        reader: R,
+    ) -> io::Result<Box<dyn Read + 'r>> {
+        Ok(match compression {
+            Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+            Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        })
+    }
+}
+
+pub struct Extractor {
+    target: PathBuf,
+    strip_components: usize,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Extractor {
+    pub fn is_requested(options: &Options) -> bool {
+        !options.extract_to.is_empty()
+    }
+
+    pub fn is_streamed(path: &str, options: &Options) -> bool {
+        Extractor::is_requested(options)
+            && Archive::from_name(path).is_some_and(|archive| archive != Archive::Zip)
+    }
+
+    pub fn new(options: &Options) -> io::Result<Extractor> {
+        let patterns = |globs: &[String]| {
+            globs
+                .iter()
+                .map(|glob| {
+                    Pattern::new(glob).map_err(|e| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Invalid glob {glob}: {e}"),
+                        )
+                    })
+                })
+                .collect::<io::Result<Vec<Pattern>>>()
+        };
+        fs::create_dir_all(&options.extract_to)?;
+        Ok(Extractor {
+            target: fs::canonicalize(&options.extract_to)?,
+            strip_components: options.strip_components,
+            include: patterns(&options.include)?,
+            exclude: patterns(&options.exclude)?,
+        })
+    }
+
+    pub fn check(path: &str, options: &Options) -> io::Result<()> {
+        Extractor::new(options)?;
+        match Archive::from_name(path) {
+            Some(_) => Ok(()),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Cannot extract {path}: unsupported archive format"),
+            )),
+        }
+    }
+
+    pub fn get_output(path: &str, silent: bool, options: &Options) -> ChannelWriter {
+        if !silent {
+            println!("Extracting to {}.", options.extract_to);
+        }
+        let archive = Archive::from_name(path).expect("Cannot determine archive format");
+        let extractor = Extractor::new(options).expect("Cannot create extraction target");
+        let name = path.to_string();
+        let (writer, mut reader) = channel(CHANNEL_CAPACITY);
+        let handle = std::thread::spawn(move || {
+            let result = extractor.unpack(&archive, &name, &mut reader);
+            // Keep consuming so that the transfer is not interrupted by a failed extraction.
+            io::copy(&mut reader, &mut io::sink())?;
+            result
+        });
+        EXTRACTIONS.lock().unwrap().insert(path.to_string(), handle);
+        writer
+    }
+
+    pub fn finish(path: &str, options: &Options) -> io::Result<()> {
+        let handle = EXTRACTIONS.lock().unwrap().remove(path);
+        if let Some(handle) = handle {
+            return handle
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("Extraction stopped unexpectedly")));
+        }
+        let archive = Archive::from_name(path).expect("Cannot determine archive format");
+        let extractor = Extractor::new(options)?;
+        match archive {
+            Archive::Zip => extractor.unpack_zip(File::open(path)?)?,
+            _ => extractor.unpack(&archive, path, File::open(path)?)?,
+        }
+        fs::remove_file(path)
+    }
+
+    fn unpack<R: Read>(&self, archive: &Archive, name: &str, reader: R) -> io::Result<()> {
+        match archive {
+            Archive::Tar(None) => self.unpack_tar(reader),
+            Archive::Tar(Some(compression)) => {
+                self.unpack_tar(Archive::decoder(compression, reader)?)
+            }
+            Archive::Single(compression) => {
+                let name = Slicer::filename_from_url(name).unwrap_or_default();
+                let name = Compression::strip_extension(&name);
+                let destination = self.prepare(&self.sanitize(Path::new(name))?)?;
+                io::copy(
+                    &mut Archive::decoder(compression, reader)?,
+                    &mut File::create(destination)?,
+                )?;
+                Ok(())
+            }
+            Archive::Zip => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Zip archives cannot be extracted from a stream",
+            )),
+        }
+    }
+
+    fn unpack_tar<R: Read>(&self, reader: R) -> io::Result<()> {
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            let Some(relative) = self.destination(&path)? else {
+                continue;
+            };
+            let link = entry.link_name()?.map(|link| link.into_owned());
+            match (entry.header().entry_type(), link) {
+                (tar::EntryType::Symlink, Some(link)) => {
+                    Extractor::check_link(&relative, &link)?;
+                    entry.unpack(self.prepare(&relative)?)?;
+                }
+                (tar::EntryType::Link, Some(link)) => {
+                    let source = self.target.join(self.strip(&self.sanitize(&link)?));
+                    if !fs::canonicalize(&source)?.starts_with(&self.target) {
+                        return Err(Extractor::unsafe_entry(&path, "links outside the target"));
+                    }
+                    let destination = self.prepare(&relative)?;
+                    if destination.exists() {
+                        fs::remove_file(&destination)?;
+                    }
+                    fs::hard_link(source, destination)?;
+                }
+                _ => {
+                    entry.unpack(self.prepare(&relative)?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn unpack_zip(&self, file: File) -> io::Result<()> {
+        let mut archive = zip::ZipArchive::new(file).map_err(io::Error::other)?;
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index).map_err(io::Error::other)?;
+            let Some(relative) = self.destination(Path::new(entry.name()))? else {
+                continue;
+            };
+            let destination = self.prepare(&relative)?;
+            if entry.is_dir() {
+                fs::create_dir_all(&destination)?;
+                continue;
+            }
+            io::copy(&mut entry, &mut File::create(&destination)?)?;
+            if let Some(mode) = entry.unix_mode() {
+                crate::io::preserve(&destination.to_string_lossy(), None, Some(mode & 0o7777))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn destination(&self, path: &Path) -> io::Result<Option<PathBuf>> {
+        let relative = self.strip(&self.sanitize(path)?);
+        if relative.as_os_str().is_empty() || !self.is_selected(&relative) {
+            return Ok(None);
+        }
+        Ok(Some(relative))
+    }
+
+    fn sanitize(&self, path: &Path) -> io::Result<PathBuf> {
+        let mut relative = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(part) => relative.push(part),
+                Component::CurDir => {}
+                _ => {
+                    return Err(Extractor::unsafe_entry(
+                        path,
+                        "is absolute or leaves the target",
+                    ))
+                }
+            }
+        }
+        Ok(relative)
+    }
+
+    fn strip(&self, path: &Path) -> PathBuf {
+        path.components().skip(self.strip_components).collect()
+    }
+
+    fn is_selected(&self, path: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches_path(path)))
+            && !self.exclude.iter().any(|p| p.matches_path(path))
+    }
+
+    fn check_link(relative: &Path, link: &Path) -> io::Result<()> {
+        let mut depth = relative.components().count() - 1;
+        for component in link.components() {
+            match component {
+                Component::Normal(_) => depth += 1,
+                Component::CurDir => {}
+                Component::ParentDir if depth > 0 => depth -= 1,
+                _ => {
+                    return Err(Extractor::unsafe_entry(
+                        relative,
+                        "links outside the target",
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn prepare(&self, relative: &Path) -> io::Result<PathBuf> {
+        let destination = self.target.join(relative);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+            if !fs::canonicalize(parent)?.starts_with(&self.target) {
+                return Err(Extractor::unsafe_entry(
+                    relative,
+                    "is placed outside the target by a symlink",
+                ));
+            }
+        }
+        if fs::symlink_metadata(&destination).is_ok_and(|m| m.file_type().is_symlink()) {
+            fs::remove_file(&destination)?;
+        }
+        Ok(destination)
+    }
+
+    fn unsafe_entry(path: &Path, reason: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Refusing to extract {}: it {reason}.", path.display()),
+        )
+    }
+}
+
+#[cfg(test)]
+fn tar_gz(entries: &[(&str, tar::EntryType, &str)]) -> Vec<u8> {
+    let encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for (path, entry_type, content) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_entry_type(*entry_type);
+        header.set_mode(0o644);
+        match entry_type {
+            tar::EntryType::Symlink => {
+                header.set_link_name(content).unwrap();
+                header.set_size(0);
+                header.set_cksum();
+                builder.append(&header, io::empty()).unwrap();
+            }
+            _ => {
+                header.set_size(content.len() as u64);
+                header.set_cksum();
+                builder.append(&header, content.as_bytes()).unwrap();
+            }
+        }
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+#[cfg(test)]
+fn extract_stream(target: &str, archive: Vec<u8>, options: Options) -> io::Result<()> {
+    use std::io::Write;
+    let options = Options {
+        extract_to: target.to_string(),
+        ..options
+    };
+    let path = target.to_string() + ".tar.gz";
+    {
+        let mut out = Extractor::get_output(&path, true, &options);
+        for chunk in archive.chunks(100) {
+            out.write_all(chunk).unwrap();
+        }
+    }
+    Extractor::finish(&path, &options)
+}
+
+#[test]
+fn test_archive_from_name_works_when_typical() {
+    assert_eq!(
+        Archive::from_name("a/b.tar.gz"),
+        Some(Archive::Tar(Some(Compression::Gzip)))
+    );
+    assert_eq!(
+        Archive::from_name("b.TZST"),
+        Some(Archive::Tar(Some(Compression::Zstd)))
+    );
+    assert_eq!(Archive::from_name("b.zip"), Some(Archive::Zip));
+    assert_eq!(
+        Archive::from_name("b.txt.xz"),
+        Some(Archive::Single(Compression::Xz))
+    );
+    assert_eq!(Archive::from_name("b.txt"), None);
+}
+
+#[test]
+fn test_extract_works_when_strip_components_and_globs() {
+    let target = "test_extract_works_when_strip_components_and_globs";
+    let archive = tar_gz(&[
+        ("release/bin/aim", tar::EntryType::Regular, "binary"),
+        ("release/README.md", tar::EntryType::Regular, "readme"),
+        ("release/doc/notes.txt", tar::EntryType::Regular, "notes"),
+    ]);
+    let options = Options {
+        strip_components: 1,
+        exclude: vec!["*.txt".to_string()],
+        ..Default::default()
+    };
+
+    let result = extract_stream(target, archive, options);
+    let binary = fs::read_to_string(Path::new(target).join("bin/aim"));
+    let readme = fs::read_to_string(Path::new(target).join("README.md"));
+    let is_notes_extracted = Path::new(target).join("doc/notes.txt").exists();
+    fs::remove_dir_all(target).unwrap();
+
+    assert!(result.is_ok());
+    assert_eq!(binary.unwrap(), "binary");
+    assert_eq!(readme.unwrap(), "readme");
+    assert!(!is_notes_extracted);
+}
+
+#[test]
+fn test_extract_fails_when_path_traversal() {
+    let target = "test_extract_fails_when_path_traversal";
+    let archive = tar_gz(&[("../evil", tar::EntryType::Regular, "evil")]);
+
+    let result = extract_stream(target, archive, Options::default());
+    fs::remove_dir_all(target).unwrap();
+
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert!(!Path::new("evil").exists());
+}
+
+#[test]
+fn test_extract_fails_when_symlink_escapes() {
+    let target = "test_extract_fails_when_symlink_escapes";
+    let archive = tar_gz(&[
+        ("link", tar::EntryType::Symlink, "../.."),
+        ("link/evil", tar::EntryType::Regular, "evil"),
+    ]);
+
+    let result = extract_stream(target, archive, Options::default());
+    let is_link_created = fs::symlink_metadata(Path::new(target).join("link")).is_ok();
+    fs::remove_dir_all(target).unwrap();
+
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert!(!is_link_created);
+}
+
+#[test]
+fn test_finish_extracts_zip_when_downloaded() {
+    use std::io::Write;
+    let target = "test_finish_extracts_zip_when_downloaded";
+    let path = target.to_string() + ".zip";
+    let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+    writer
+        .start_file("dir/file.txt", zip::write::SimpleFileOptions::default())
+        .unwrap();
+    writer.write_all(b"zipped").unwrap();
+    writer.finish().unwrap();
+    let options = Options {
+        extract_to: target.to_string(),
+        ..Default::default()
+    };
+
+    let result = Extractor::finish(&path, &options);
+    let content = fs::read_to_string(Path::new(target).join("dir/file.txt"));
+    fs::remove_dir_all(target).unwrap();
+
+    assert!(result.is_ok());
+    assert_eq!(content.unwrap(), "zipped");
+    assert!(!Path::new(&path).exists());
+}
@@ -1,16 +1,28 @@
-use async_ftp::{types::FileType, FtpStream};
 use futures_util::StreamExt;
 use std::cmp::min;
 use std::io::{Error, Write};
-use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
+use std::time::UNIX_EPOCH;
+use suppaftp::async_native_tls::TlsConnector;
+use suppaftp::list::{File, ListParser};
+use suppaftp::tokio::TransferStream;
+use suppaftp::tokio::{AsyncNativeTlsConnector, AsyncNativeTlsFtpStream, AsyncNativeTlsStream};
+use suppaftp::types::FileType;
+use suppaftp::{FtpError, Mode, Status};
+use tokio::io::AsyncReadExt;
 use tokio_util::io::ReaderStream;
 
 use crate::address::ParsedAddress;
 use crate::bar::WrappedBar;
 use crate::consts::*;
+use crate::driver::{Options, RemoteMetadata};
 use crate::error::ValidateError;
 use crate::hash::HashChecker;
-use crate::io::get_output;
+use crate::io::{open_input, open_output, ChannelWriter, Input, Output};
+use crate::question::{ask_password, QuestionTrait, QuestionWrapped};
+use crate::timeout::{timeout_from_io, SpeedGuard};
+use crate::tls::connector_builder;
+
+type FtpStream = AsyncNativeTlsFtpStream;
 
 pub struct FTPHandler {
     pub output: Box<dyn Write>,
@@ -18,10 +30,10 @@ pub struct FTPHandler {
 }
 
 struct FTPGetProperties {
-    out: Box<dyn Write + Send>,
+    out: Output,
     transferred: u64,
     total_size: u64,
-    reader: tokio::io::BufReader<async_ftp::DataStream>,
+    reader: TransferStream<AsyncNativeTlsStream>,
 }
 
 impl FTPHandler {
@@ -30,8 +42,10 @@ impl FTPHandler {
         output: &str,
         bar: &mut WrappedBar,
         expected_sha256: &str,
+        sink: Option<ChannelWriter>,
+        options: &Options,
     ) -> Result<(), ValidateError> {
-        FTPHandler::_get(input, output, bar).await?;
+        FTPHandler::_get(input, output, bar, sink, options).await?;
         HashChecker::check(output, expected_sha256)
     }
 
@@ -39,22 +53,22 @@ impl FTPHandler {
         input: &str,
         output: &str,
         bar: &mut WrappedBar,
-    ) -> Result<FTPGetProperties, Box<dyn std::error::Error>> {
-        let (out, transferred) = get_output(output, bar.silent);
+        sink: Option<ChannelWriter>,
+        options: &Options,
+    ) -> Result<FTPGetProperties, ValidateError> {
+        let (out, transferred) = open_output(input, output, bar.silent, options, sink);
 
         let parsed_address = ParsedAddress::parse_address(input, bar.silent);
-        let mut ftp_stream = FTPHandler::get_stream(transferred, &parsed_address)
-            .await
-            .expect("Cannot get FTP stream");
+        let mut ftp_stream =
+            FTPHandler::get_stream(transferred, input, &parsed_address, options, false).await?;
         let total_size = ftp_stream
             .size(&parsed_address.file)
             .await
-            .unwrap()
-            .unwrap() as u64;
+            .expect("Cannot determine remote FTP file size") as u64;
 
         bar.set_length(total_size);
         let reader = ftp_stream
-            .get(&parsed_address.file)
+            .retr_as_stream(&parsed_address.file)
             .await
             .expect("Cannot download file via FTP");
         Ok(FTPGetProperties {
@@ -65,21 +79,30 @@ impl FTPHandler {
         })
     }
 
-    async fn _get(input: &str, output: &str, bar: &mut WrappedBar) -> Result<(), ValidateError> {
-        let mut properties = FTPHandler::setup(input, output, bar).await.unwrap();
+    async fn _get(
+        input: &str,
+        output: &str,
+        bar: &mut WrappedBar,
+        sink: Option<ChannelWriter>,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        let mut properties = FTPHandler::setup(input, output, bar, sink, options).await?;
+        let mut guard = SpeedGuard::new(&options.timeouts);
         loop {
             let mut buffer = vec![0; BUFFER_SIZE];
-            let byte_count = properties
-                .reader
-                .read(&mut buffer[..])
-                .await
+            let byte_count = options
+                .timeouts
+                .read(properties.reader.read(&mut buffer[..]))
+                .await?
                 .expect("Cannot read FTP stream");
+            guard.update(byte_count as u64)?;
 
             buffer.truncate(byte_count);
             if !buffer.is_empty() {
                 properties
                     .out
                     .write_all(&buffer)
+                    .await
                     .map_err(|_| "Error while writing to output.")
                     .unwrap();
                 let new = min(
@@ -92,118 +115,631 @@ impl FTPHandler {
                 break;
             }
         }
+        properties
+            .reader
+            .finish()
+            .await
+            .expect("Cannot finish FTP download");
 
         bar.finish_download(input, output);
         Ok(())
     }
 
-    pub async fn put(input: &str, output: &str, mut bar: WrappedBar) -> Result<(), ValidateError> {
-        let mut file = tokio::fs::File::open(&input)
+    pub async fn put(
+        input: &str,
+        output: &str,
+        mut bar: WrappedBar,
+        source: Option<Input>,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        let source = open_input(input, source)
             .await
             .expect("Cannot read input file");
-        let total_size = file
-            .metadata()
-            .await
-            .expect("Cannot determine input file length")
-            .len();
+        let is_resumable = source.is_resumable();
+        let Input {
+            reader: mut file,
+            size: total_size,
+            ..
+        } = source;
 
         let parsed_address = ParsedAddress::parse_address(output, bar.silent);
-        let transferred = FTPHandler::get_already_uploaded(output, bar.silent).await;
-        file.seek(SeekFrom::Current(transferred as i64))
-            .await
-            .expect("Cannot seek in SFTP file");
-        let mut ftp_stream = FTPHandler::get_stream(transferred, &parsed_address)
+        let transferred = match is_resumable {
+            true => FTPHandler::get_already_uploaded(output, bar.silent, options).await?,
+            false => 0,
+        };
+        file.skip(transferred)
             .await
-            .expect("Cannot get stream");
+            .expect("Cannot seek in input file");
+        let mut ftp_stream = FTPHandler::get_stream(
+            transferred,
+            output,
+            &parsed_address,
+            options,
+            options.create_dirs,
+        )
+        .await?;
         let mut reader_stream = ReaderStream::new(file);
 
-        bar.set_length(total_size);
+        bar.set_size(total_size);
         let mut uploaded = transferred;
+        let mut guard = SpeedGuard::new(&options.timeouts);
 
         let async_stream = async_stream::stream! {
             while let Some(chunk) = reader_stream.next().await {
                 if let Ok(chunk) = &chunk {
-                    let new = min(uploaded + (chunk.len() as u64), total_size);
-                    uploaded = new;
-                    bar.set_position(new);
-                    if uploaded >= total_size {
+                    if let Err(e) = guard.update(chunk.len() as u64) {
+                        yield Err(e.into());
+                        break;
+                    }
+                    let new = uploaded + (chunk.len() as u64);
+                    uploaded = total_size.map_or(new, |total_size| min(new, total_size));
+                    bar.set_position(uploaded);
+                    if total_size.is_some_and(|total_size| uploaded >= total_size) {
                         bar.finish_upload(input, output);
                     }
                 }
                 yield chunk;
             }
+            if total_size.is_none() {
+                bar.finish_upload(input, output);
+            }
         };
 
         let stream_reader = tokio_util::io::StreamReader::new(async_stream);
         tokio::pin!(stream_reader);
-        ftp_stream
-            .put(&parsed_address.file, &mut stream_reader)
-            .await
-            .expect("Cannot upload file via FTP");
+        let result = ftp_stream
+            .put_file(&parsed_address.file, &mut stream_reader)
+            .await;
+        if let Err(FtpError::ConnectionError(e)) = &result {
+            if let Some(timeout) = timeout_from_io(e) {
+                return Err(timeout.into());
+            }
+        }
+        result.expect("Cannot upload file via FTP");
 
         Ok(())
     }
 
-    async fn get_already_uploaded(output: &str, silent: bool) -> u64 {
+    async fn get_already_uploaded(
+        output: &str,
+        silent: bool,
+        options: &Options,
+    ) -> Result<u64, ValidateError> {
         let parsed_address = ParsedAddress::parse_address(output, silent);
-        let mut ftp_stream = FtpStream::connect((parsed_address).server.clone())
-            .await
-            .expect("Cannot connect to FTP server");
-        ftp_stream
-            .login(&parsed_address.username, &parsed_address.password)
-            .await
-            .expect("Cannot login to FTP server");
+        let mut ftp_stream = FTPHandler::connect(output, &parsed_address, options).await?;
+        FTPHandler::cwd(
+            &mut ftp_stream,
+            &parsed_address.path_segments,
+            options.create_dirs,
+        )
+        .await;
+        Ok(ftp_stream.size(&parsed_address.file).await.unwrap_or(0) as u64)
+    }
 
-        for path in &parsed_address.path_segments {
-            ftp_stream
-                .cwd(path)
-                .await
-                .expect("Path in FTP URL does not exist on remote");
+    pub async fn stat(
+        input: &str,
+        options: &Options,
+    ) -> Result<Option<RemoteMetadata>, ValidateError> {
+        let parsed_address = ParsedAddress::parse_address(input, true);
+        let mut ftp_stream =
+            FTPHandler::get_stream(0, input, &parsed_address, options, false).await?;
+        let file = &parsed_address.file;
+        if let Ok(line) = ftp_stream.mlst(Some(file)).await {
+            if let Ok(entry) = ListParser::parse_mlst(&line) {
+                let _ = ftp_stream.quit().await;
+                return Ok(Some(FTPHandler::metadata(&entry)));
+            }
         }
-        ftp_stream
-            .size(&parsed_address.file)
-            .await
-            .unwrap_or(Some(0))
-            .unwrap() as u64
+        let size = ftp_stream.size(file).await;
+        let modified = ftp_stream.mdtm(file).await;
+        let _ = ftp_stream.quit().await;
+        Ok(match size {
+            Ok(size) => Some(RemoteMetadata {
+                size: Some(size as u64),
+                modified: modified.ok().map(|m| m.and_utc().into()),
+                ..Default::default()
+            }),
+            Err(_) => None,
+        })
+    }
+
+    fn metadata(entry: &File) -> RemoteMetadata {
+        RemoteMetadata {
+            size: entry.is_file().then_some(entry.size() as u64),
+            modified: Some(entry.modified()).filter(|&modified| modified != UNIX_EPOCH),
+            ..Default::default()
+        }
+    }
+
+    /// Lists the current folder with MLSD, falling back to LIST in Unix or
+    /// DOS format for servers without it.
+    async fn list(ftp_stream: &mut FtpStream) -> Vec<File> {
+        let entries = match ftp_stream.mlsd(None).await {
+            Ok(lines) => lines
+                .iter()
+                .filter_map(|line| ListParser::parse_mlsd(line).ok())
+                .collect::<Vec<_>>(),
+            Err(_) => ftp_stream
+                .list(None)
+                .await
+                .expect("Cannot list FTP folder")
+                .iter()
+                .filter_map(|line| {
+                    ListParser::parse_posix(line)
+                        .or_else(|_| ListParser::parse_dos(line))
+                        .ok()
+                })
+                .collect(),
+        };
+        entries
+            .into_iter()
+            .filter(|entry| !matches!(entry.name(), "." | ".."))
+            .collect()
     }
 
     async fn get_stream(
         transferred: u64,
+        address: &str,
         parsed_address: &ParsedAddress,
-    ) -> Result<async_ftp::FtpStream, async_ftp::FtpError> {
-        let mut ftp_stream = FtpStream::connect((parsed_address).server.clone())
+        options: &Options,
+        create_dirs: bool,
+    ) -> Result<FtpStream, ValidateError> {
+        let mut ftp_stream = FTPHandler::connect(address, parsed_address, options).await?;
+        FTPHandler::cwd(&mut ftp_stream, &parsed_address.path_segments, create_dirs).await;
+
+        ftp_stream
+            .transfer_type(FileType::Binary)
             .await
-            .expect("Cannot connect to FTP server");
+            .expect("Cannot set transfer type to binary");
         ftp_stream
-            .login(&parsed_address.username, &parsed_address.password)
+            .resume_transfer(transferred as usize)
             .await
-            .expect("Cannot login to FTP server");
+            .expect("Cannot restart transfer from already transferred byte count");
+        Ok(ftp_stream)
+    }
+
+    /// Connects and logs in, over implicit TLS for `ftps://` or upgrading
+    /// `ftp://` with `AUTH TLS` as requested by `--ftp-ssl`.
+    async fn connect(
+        address: &str,
+        parsed_address: &ParsedAddress,
+        options: &Options,
+    ) -> Result<FtpStream, ValidateError> {
+        let server = &parsed_address.server;
+        let domain = FTPHandler::domain(server);
+        let mut ftp_stream = match address.starts_with("ftps://") {
+            true => FTPHandler::connect_implicit(server, domain, options).await?,
+            false => FTPHandler::connect_explicit(server, domain, options).await?,
+        };
+        ftp_stream.set_mode(match options.ftp_mode.as_str() {
+            "active" => Mode::Active,
+            "epsv" => Mode::ExtendedPassive,
+            _ => Mode::Passive,
+        });
+        FTPHandler::login(&mut ftp_stream, parsed_address).await?;
+        Ok(ftp_stream)
+    }
 
-        for path in &parsed_address.path_segments {
+    async fn connect_implicit(
+        server: &str,
+        domain: &str,
+        options: &Options,
+    ) -> Result<FtpStream, ValidateError> {
+        let mut ftp_stream = options
+            .timeouts
+            .connect(FtpStream::connect_secure_implicit(
+                server,
+                FTPHandler::tls(options),
+                domain,
+            ))
+            .await?
+            .unwrap_or_else(|e| panic!("Cannot connect to FTPS server: {e}"));
+        // Unlike AUTH TLS, implicit TLS leaves the data channel in clear until asked.
+        for command in ["PBSZ 0", "PROT P"] {
             ftp_stream
-                .cwd(path)
+                .custom_command(command, &[Status::CommandOk])
                 .await
-                .expect("Path in FTP URL does not exist on remote");
+                .unwrap_or_else(|e| panic!("Cannot protect FTPS data channel: {e}"));
         }
+        Ok(ftp_stream)
+    }
 
-        ftp_stream
-            .transfer_type(FileType::Binary)
+    async fn connect_explicit(
+        server: &str,
+        domain: &str,
+        options: &Options,
+    ) -> Result<FtpStream, ValidateError> {
+        let connect = || async {
+            options
+                .timeouts
+                .connect(FtpStream::connect(server))
+                .await
+                .map(|result| result.expect("Cannot connect to FTP server"))
+        };
+        let ftp_stream = connect().await?;
+        if matches!(options.ftp_ssl.as_str(), "" | "off") {
+            return Ok(ftp_stream);
+        }
+        match ftp_stream
+            .into_secure(FTPHandler::tls(options), domain)
             .await
-            .expect("Cannot set transfer type to binary");
-        ftp_stream
-            .restart_from(transferred)
+        {
+            Ok(ftp_stream) => Ok(ftp_stream),
+            Err(FtpError::UnexpectedResponse(response)) if options.ftp_ssl == "try" => {
+                if !options.silent {
+                    eprintln!(
+                        "⚠️ Server refused AUTH TLS ({}), continuing in plain text.",
+                        response.status.code()
+                    );
+                }
+                Ok(connect().await?)
+            }
+            Err(e) => panic!("Cannot secure FTP connection with AUTH TLS: {e}"),
+        }
+    }
+
+    fn tls(options: &Options) -> AsyncNativeTlsConnector {
+        AsyncNativeTlsConnector::from(TlsConnector::from(connector_builder(options)))
+    }
+
+    fn domain(server: &str) -> &str {
+        let host = server.rsplit_once(':').map_or(server, |(host, _)| host);
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+
+    async fn cwd(ftp_stream: &mut FtpStream, path_segments: &[String], create_dirs: bool) {
+        for path in path_segments {
+            if ftp_stream.cwd(path).await.is_ok() {
+                continue;
+            }
+            if !create_dirs {
+                panic!("Path in FTP URL does not exist on remote");
+            }
+            ftp_stream
+                .mkdir(path)
+                .await
+                .unwrap_or_else(|e| panic!("Cannot create remote FTP folder {path}: {e}"));
+            ftp_stream
+                .cwd(path)
+                .await
+                .expect("Cannot enter created remote FTP folder");
+        }
+    }
+
+    async fn login(
+        ftp_stream: &mut FtpStream,
+        parsed_address: &ParsedAddress,
+    ) -> Result<(), ValidateError> {
+        let (username, server) = (&parsed_address.username, &parsed_address.server);
+        if ftp_stream
+            .login(username.as_str(), parsed_address.password.as_str())
             .await
-            .expect("Cannot restart transfer from already transferred byte count");
-        Ok(ftp_stream)
+            .is_ok()
+        {
+            return Ok(());
+        }
+        let username = match username.as_str() {
+            "anonymous" => QuestionWrapped::ask(&format!("Name for {server}: ")),
+            username => Some(username.to_string()),
+        };
+        let credentials = username.and_then(|username| {
+            let password = ask_password::<QuestionWrapped>(&username, server)?;
+            Some((username, password))
+        });
+        if let Some((username, password)) = credentials {
+            if ftp_stream
+                .login(username.as_str(), password.as_str())
+                .await
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+        Err(ValidateError::Unauthorized {
+            host: format!("{}@{server}", parsed_address.username),
+        })
     }
 
-    pub async fn get_links(_input: String) -> Result<Vec<String>, Error> {
-        panic!("Unimplemented");
+    pub async fn get_links(input: String, options: &Options) -> Result<Vec<String>, Error> {
+        let parsed_address = ParsedAddress::parse_address(&input, true);
+        let mut ftp_stream = FTPHandler::connect(&input, &parsed_address, options).await?;
+        let folders: Vec<String> = parsed_address
+            .path_segments
+            .into_iter()
+            .chain([parsed_address.file])
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        FTPHandler::cwd(&mut ftp_stream, &folders, false).await;
+
+        let prefix = folders
+            .iter()
+            .map(|folder| folder.to_string() + "/")
+            .collect::<String>();
+        let mut result: Vec<String> = FTPHandler::list(&mut ftp_stream)
+            .await
+            .iter()
+            .map(|entry| match entry.is_directory() {
+                true => format!("{prefix}{}/", entry.name()),
+                false => format!("{prefix}{}", entry.name()),
+            })
+            .collect();
+        let _ = ftp_stream.quit().await;
+        result.push("..".to_string());
+
+        result.sort();
+
+        Ok(result)
     }
 }
 
 #[tokio::test]
-#[should_panic]
-async fn test_should_panic_when_not_implemented() {
-    let _ = FTPHandler::get_links("dummy".to_string()).await;
+async fn test_get_stream_times_out_when_server_is_silent() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = listener.local_addr().unwrap().to_string();
+    let _handle = tokio::spawn(async move {
+        let (_socket, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+    });
+    let parsed_address = ParsedAddress {
+        server,
+        username: "anonymous".to_string(),
+        password: "anonymous".to_string(),
+        path_segments: vec![],
+        file: "file".to_string(),
+    };
+    let options = Options {
+        timeouts: crate::timeout::Timeouts {
+            connect: Some(std::time::Duration::from_millis(100)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let result = FTPHandler::get_stream(0, "ftp://", &parsed_address, &options, false).await;
+
+    assert!(matches!(
+        result,
+        Err(ValidateError::Timeout {
+            source: crate::error::TimeoutError::Connect
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_get_stream_fails_when_login_rejected() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = listener.local_addr().unwrap().to_string();
+    let _handle = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = socket.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"220 ready\r\n").await.unwrap();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let reply: &[u8] = match line.starts_with("USER") {
+                true => b"331 password required\r\n",
+                false => b"530 login incorrect\r\n",
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+    });
+    let parsed_address = ParsedAddress {
+        server,
+        username: "user".to_string(),
+        password: "anonymous".to_string(),
+        path_segments: vec![],
+        file: "file".to_string(),
+    };
+
+    let result =
+        FTPHandler::get_stream(0, "ftp://", &parsed_address, &Options::default(), false).await;
+
+    assert!(matches!(result, Err(ValidateError::Unauthorized { .. })));
+}
+
+#[tokio::test]
+async fn test_cwd_creates_dirs_when_missing() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = listener.local_addr().unwrap().to_string();
+    let handle = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = socket.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut created = vec![];
+        writer.write_all(b"220 ready\r\n").await.unwrap();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let (command, argument) = line.split_once(' ').unwrap_or((&line, ""));
+            let reply: &[u8] = match command {
+                "CWD" if created.contains(&argument.to_string()) => b"250 ok\r\n",
+                "CWD" => b"550 no such folder\r\n",
+                "MKD" => {
+                    created.push(argument.to_string());
+                    b"257 created\r\n"
+                }
+                _ => b"500 unknown\r\n",
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+        created
+    });
+    let mut ftp_stream = FtpStream::connect(server).await.unwrap();
+
+    FTPHandler::cwd(
+        &mut ftp_stream,
+        &["releases".to_string(), "2026-10-19".to_string()],
+        true,
+    )
+    .await;
+    drop(ftp_stream);
+
+    assert_eq!(handle.await.unwrap(), vec!["releases", "2026-10-19"]);
+}
+
+#[tokio::test]
+async fn test_connect_falls_back_to_plain_when_auth_tls_refused() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = listener.local_addr().unwrap().to_string();
+    let handle = tokio::spawn(async move {
+        let mut commands = vec![];
+        for _ in 0..2 {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = socket.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 ready\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply: &[u8] = match line.split(' ').next().unwrap() {
+                    "AUTH" => b"502 not implemented\r\n",
+                    "USER" => b"331 password required\r\n",
+                    "PASS" => b"230 logged in\r\n",
+                    _ => b"500 unknown\r\n",
+                };
+                writer.write_all(reply).await.unwrap();
+                let is_done = line.starts_with("AUTH") || line.starts_with("PASS");
+                commands.push(line);
+                if is_done {
+                    break;
+                }
+            }
+        }
+        commands
+    });
+    let parsed_address = ParsedAddress {
+        server,
+        username: "user".to_string(),
+        password: "pass".to_string(),
+        path_segments: vec![],
+        file: "file".to_string(),
+    };
+    let options = Options {
+        silent: true,
+        ftp_ssl: "try".to_string(),
+        ..Default::default()
+    };
+
+    let result = FTPHandler::connect("ftp://", &parsed_address, &options).await;
+
+    assert!(result.is_ok());
+    assert_eq!(
+        handle.await.unwrap(),
+        vec!["AUTH TLS", "USER user", "PASS pass"]
+    );
+}
+
+#[test]
+fn test_domain_works_when_typical() {
+    assert_eq!(FTPHandler::domain("ftp.example.com:990"), "ftp.example.com");
+    assert_eq!(FTPHandler::domain("[::1]:21"), "::1");
+}
+
+#[cfg(test)]
+async fn serve_listing(
+    mlsx: bool,
+    listing: &'static str,
+) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = listener.local_addr().unwrap().to_string();
+    let handle = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = socket.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut commands = vec![];
+        let mut data = None;
+        writer.write_all(b"220 ready\r\n").await.unwrap();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let command = line.split(' ').next().unwrap().to_string();
+            commands.push(line);
+            let reply = match command.as_str() {
+                "USER" => "331 password required\r\n".to_string(),
+                "PASS" => "230 logged in\r\n".to_string(),
+                "TYPE" => "200 ok\r\n".to_string(),
+                "REST" => "350 ok\r\n".to_string(),
+                "PASV" | "EPSV" => {
+                    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                    let port = listener.local_addr().unwrap().port();
+                    data = Some(listener);
+                    match command.as_str() {
+                        "PASV" => format!(
+                            "227 Entering Passive Mode (127,0,0,1,{},{})\r\n",
+                            port / 256,
+                            port % 256
+                        ),
+                        _ => format!("229 Entering Extended Passive Mode (|||{port}|)\r\n"),
+                    }
+                }
+                "MLSD" | "MLST" if !mlsx => "500 unknown command\r\n".to_string(),
+                "MLST" => format!("250-Listing\r\n {listing}\r\n250 End\r\n"),
+                "MLSD" | "LIST" => {
+                    writer.write_all(b"150 here it comes\r\n").await.unwrap();
+                    let (mut socket, _) = data.take().unwrap().accept().await.unwrap();
+                    socket.write_all(listing.as_bytes()).await.unwrap();
+                    drop(socket);
+                    "226 done\r\n".to_string()
+                }
+                "QUIT" => "221 bye\r\n".to_string(),
+                _ => "502 not implemented\r\n".to_string(),
+            };
+            writer.write_all(reply.as_bytes()).await.unwrap();
+        }
+        commands
+    });
+    (server, handle)
+}
+
+#[tokio::test]
+async fn test_get_links_works_when_mlsd_and_epsv() {
+    let listing = "type=cdir;modify=20261019101500; .\r\n\
+type=dir;modify=20261019101500; releases\r\n\
+type=file;size=129;modify=20261019101500; notes.txt\r\n";
+    let (server, handle) = serve_listing(true, listing).await;
+    let options = Options {
+        ftp_mode: "epsv".to_string(),
+        ..Default::default()
+    };
+
+    let actual = FTPHandler::get_links(format!("ftp://user:pass@{server}/"), &options)
+        .await
+        .unwrap();
+
+    assert_eq!(actual, vec!["..", "notes.txt", "releases/"]);
+    let commands = handle.await.unwrap();
+    assert!(commands.contains(&"EPSV".to_string()));
+    assert!(commands.contains(&"MLSD".to_string()));
+}
+
+#[tokio::test]
+async fn test_get_links_falls_back_to_list_when_mlsd_unsupported() {
+    let listing = "drwxr-xr-x    2 1000     1000         4096 Oct 19 10:15 releases\r\n\
+10-19-26  10:15AM                  129 notes.txt\r\n";
+    let (server, handle) = serve_listing(false, listing).await;
+
+    let actual = FTPHandler::get_links(format!("ftp://user:pass@{server}/"), &Options::default())
+        .await
+        .unwrap();
+
+    assert_eq!(actual, vec!["..", "notes.txt", "releases/"]);
+    assert!(handle.await.unwrap().contains(&"LIST".to_string()));
+}
+
+#[tokio::test]
+async fn test_stat_works_when_mlst() {
+    let (server, handle) =
+        serve_listing(true, "type=file;size=129;modify=20261019101500; notes.txt").await;
+
+    let actual = FTPHandler::stat(
+        &format!("ftp://user:pass@{server}/notes.txt"),
+        &Options::default(),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    assert_eq!(actual.size, Some(129));
+    assert_eq!(
+        actual.modified,
+        Some(UNIX_EPOCH + std::time::Duration::from_secs(1_792_404_900))
+    );
+    assert!(!handle.await.unwrap().iter().any(|c| c.starts_with("SIZE")));
 }
@@ -7,22 +7,33 @@ use crate::error::ValidateError;
 pub struct HashChecker;
 impl HashChecker {
     pub fn check(filename: &str, expected_hash: &str) -> Result<(), ValidateError> {
-        let mut result = Ok(());
-        if filename != "stdout" && (!expected_hash.is_empty()) {
-            let actual_hash = HashChecker::sha256sum(filename);
-            if actual_hash != expected_hash {
-                result = Err(ValidateError::Sha256Mismatch);
-            }
-            match result {
-                Ok(()) => println!("✅ Checksum OK."),
-                Err(ValidateError::Sha256Mismatch) => println!(
-                    "❌ Checksum verification failed for {filename}:\n  expected: {expected_hash}\n  got:      {actual_hash}"),
-            }
+        let compressed_hash = crate::io::take_compressed_sha256(filename);
+        if filename == "stdout" || expected_hash.is_empty() {
+            return Ok(());
+        }
+        let actual_hash = match (std::path::Path::new(filename).exists(), compressed_hash) {
+            (_, Some(hash)) if hash == expected_hash => hash,
+            (true, _) => HashChecker::sha256sum(filename),
+            (false, hash) => hash.unwrap_or_default(),
+        };
+        HashChecker::compare(filename, &actual_hash, expected_hash)
+    }
+
+    pub fn compare(
+        name: &str,
+        actual_hash: &str,
+        expected_hash: &str,
+    ) -> Result<(), ValidateError> {
+        if actual_hash == expected_hash {
+            println!("✅ Checksum OK.");
+            return Ok(());
         }
-        result
+        println!(
+            "❌ Checksum verification failed for {name}:\n  expected: {expected_hash}\n  got:      {actual_hash}");
+        Err(ValidateError::Sha256Mismatch)
     }
 
-    fn sha256sum(filename: &str) -> String {
+    pub fn sha256sum(filename: &str) -> String {
         let mut hasher = Sha256::new();
         let mut file = fs::File::open(filename).unwrap();
 
@@ -1,16 +1,54 @@
-use futures_util::StreamExt;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures_util::{Stream, StreamExt};
 use regex::Regex;
-use reqwest::Client;
+use reqwest::header::{
+    ACCEPT_ENCODING, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION,
+};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Certificate, Client, Method, Response, StatusCode};
 use std::cmp::min;
+use std::collections::HashMap;
 use std::io::Error;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::io::AsyncRead;
+use tokio_util::bytes::Bytes;
 use tokio_util::io::ReaderStream;
 
 use crate::address::ParsedAddress;
 use crate::bar::WrappedBar;
 use crate::consts::*;
-use crate::error::ValidateError;
+use crate::driver::{Options, RemoteMetadata};
+use crate::error::{TimeoutError, ValidateError};
 use crate::hash::HashChecker;
 use crate::io;
+use crate::slicer::Slicer;
+use crate::state::State;
+use crate::timeout::{timeout_from_io, SpeedGuard, Timeouts};
+use crate::tls::ca_bundle;
+
+const TUS_RESUMABLE: &str = "Tus-Resumable";
+const TUS_STATE: &str = "tus";
+const TUS_VERSION: &str = "1.0.0";
+
+pub fn parse_http_date(date: &str) -> Option<SystemTime> {
+    chrono::DateTime::parse_from_rfc2822(date)
+        .ok()
+        .map(|date| date.into())
+}
+
+fn format_http_date(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+enum FormValue {
+    Text(String),
+    File(String),
+}
 
 pub struct HTTPSHandler;
 impl HTTPSHandler {
@@ -19,72 +57,478 @@ impl HTTPSHandler {
         output: &str,
         bar: &mut WrappedBar,
         expected_sha256: &str,
-        no_follow_redirects: bool,
+        sink: Option<io::ChannelWriter>,
+        options: &Options,
     ) -> Result<(), ValidateError> {
-        HTTPSHandler::_get(input, output, bar, no_follow_redirects).await?;
+        HTTPSHandler::_get(input, output, bar, sink, options).await?;
         HashChecker::check(output, expected_sha256)
     }
 
-    pub async fn put(input: &str, output: &str, mut bar: WrappedBar, no_follow_redirects: bool) -> Result<(), ValidateError> {
+    pub async fn put(
+        input: &str,
+        output: &str,
+        bar: WrappedBar,
+        source: Option<io::Input>,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        if !options.form.is_empty() {
+            return HTTPSHandler::put_multipart(input, output, bar, options).await;
+        }
+        let source = io::open_input(input, source)
+            .await
+            .expect("Cannot open input for HTTPS read");
+        match (options.upload_resume.as_str(), source.is_resumable()) {
+            ("tus", true) => HTTPSHandler::put_tus(input, source, output, bar, options).await,
+            ("tus", false) => Err(ValidateError::Unsupported {
+                reason: format!("tus uploads need a seekable file, {input} is not."),
+            }),
+            _ => HTTPSHandler::put_raw(input, source, output, bar, options).await,
+        }
+    }
+
+    async fn put_raw(
+        input: &str,
+        source: io::Input,
+        output: &str,
+        mut bar: WrappedBar,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
         let parsed_address = ParsedAddress::parse_address(output, bar.silent);
-        let file = tokio::fs::File::open(&input)
+        let is_resumable = source.is_resumable();
+        let io::Input {
+            reader: mut file,
+            size: total_size,
+            ..
+        } = source;
+
+        let uploaded = match options.upload_resume == "content-range" && is_resumable {
+            true => HTTPSHandler::get_already_uploaded(output, bar.silent, options).await,
+            false => 0,
+        };
+        if uploaded > 0 && total_size == Some(uploaded) {
+            bar.set_size(total_size);
+            bar.set_position(uploaded);
+            bar.finish_upload(input, output);
+            return Ok(());
+        }
+        let uploaded = match total_size.is_some_and(|total_size| uploaded < total_size) {
+            true => uploaded,
+            false => 0,
+        };
+        file.skip(uploaded)
             .await
-            .expect("Cannot open input file for HTTPS read");
-        let total_size = file
-            .metadata()
+            .expect("Cannot seek in input file");
+        bar.set_size(total_size);
+        let bar = Arc::new(bar);
+        let async_stream = HTTPSHandler::progress_stream(
+            file,
+            uploaded,
+            total_size,
+            bar,
+            input.to_string(),
+            output.to_string(),
+            options.timeouts,
+        );
+
+        let mut request = HTTPSHandler::client(options)
+            .request(HTTPSHandler::method(options, Method::PUT), output)
+            .header(CONTENT_TYPE, HTTPSHandler::content_type(input));
+        if let Some(total_size) = total_size {
+            request = request.header(CONTENT_LENGTH, total_size - uploaded);
+            if uploaded > 0 {
+                request = request.header(
+                    CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", uploaded, total_size - 1, total_size),
+                );
+            }
+        }
+        let response = request
+            .header(
+                reqwest::header::USER_AGENT,
+                reqwest::header::HeaderValue::from_static(CLIENT_ID),
+            )
+            .basic_auth(parsed_address.username, Some(parsed_address.password))
+            .body(reqwest::Body::wrap_stream(async_stream))
+            .send()
+            .await;
+        let response = HTTPSHandler::check_send(response, "Failed to PUT")?;
+        HTTPSHandler::print_response(response, output, options.silent).await
+    }
+
+    async fn put_tus(
+        input: &str,
+        source: io::Input,
+        output: &str,
+        mut bar: WrappedBar,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        let parsed_address = ParsedAddress::parse_address(output, bar.silent);
+        let io::Input {
+            reader: mut file,
+            size: total_size,
+            ..
+        } = source;
+        let total_size = total_size.expect("Cannot determine input file size for tus upload");
+        let client = HTTPSHandler::client(options);
+        let state_key = HTTPSHandler::tus_state_key(input, total_size, output);
+
+        let saved_location =
+            State::load(TUS_STATE, &state_key).and_then(|values| values.get("location").cloned());
+        let resumed = match saved_location {
+            Some(location) => HTTPSHandler::tus_offset(&client, &location, &parsed_address)
+                .await
+                .map(|offset| (location, offset)),
+            None => None,
+        };
+        let (location, uploaded) = match resumed {
+            Some(resumed) => resumed,
+            None => {
+                let location =
+                    HTTPSHandler::tus_create(&client, input, output, total_size, &parsed_address)
+                        .await?;
+                State::save(
+                    TUS_STATE,
+                    &state_key,
+                    &HashMap::from([("location".to_string(), location.clone())]),
+                );
+                (location, 0)
+            }
+        };
+
+        file.skip(uploaded)
             .await
-            .expect("Cannot determine input file size for HTTPS read")
-            .len();
-        let input_ = input.to_string();
-        let output_ = output.to_string();
-        let mut reader_stream = ReaderStream::new(file);
+            .expect("Cannot seek in input file");
+        bar.set_length(total_size);
+        let bar = Arc::new(bar);
+        let async_stream = HTTPSHandler::progress_stream(
+            file,
+            uploaded,
+            Some(total_size),
+            bar,
+            input.to_string(),
+            output.to_string(),
+            options.timeouts,
+        );
 
-        let mut uploaded = HTTPSHandler::get_already_uploaded(output, bar.silent, no_follow_redirects).await;
+        let response = client
+            .patch(&location)
+            .header(TUS_RESUMABLE, TUS_VERSION)
+            .header("Upload-Offset", uploaded)
+            .header(CONTENT_TYPE, "application/offset+octet-stream")
+            .header(CONTENT_LENGTH, total_size - uploaded)
+            .header(
+                reqwest::header::USER_AGENT,
+                reqwest::header::HeaderValue::from_static(CLIENT_ID),
+            )
+            .basic_auth(parsed_address.username, Some(parsed_address.password))
+            .body(reqwest::Body::wrap_stream(async_stream))
+            .send()
+            .await;
+        let response = HTTPSHandler::check_send(response, "Failed to PATCH tus upload")?;
+        HTTPSHandler::check_status(&response, &location)?;
+        State::remove(TUS_STATE, &state_key);
+        Ok(())
+    }
+
+    async fn tus_create(
+        client: &Client,
+        input: &str,
+        output: &str,
+        total_size: u64,
+        parsed_address: &ParsedAddress,
+    ) -> Result<String, ValidateError> {
+        let filename = std::path::Path::new(input)
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let response = client
+            .post(output)
+            .header(TUS_RESUMABLE, TUS_VERSION)
+            .header("Upload-Length", total_size)
+            .header(
+                "Upload-Metadata",
+                "filename ".to_string() + &STANDARD.encode(filename),
+            )
+            .header(
+                reqwest::header::USER_AGENT,
+                reqwest::header::HeaderValue::from_static(CLIENT_ID),
+            )
+            .basic_auth(&parsed_address.username, Some(&parsed_address.password))
+            .send()
+            .await;
+        let response = HTTPSHandler::check_send(response, "Failed to create tus upload")?;
+        HTTPSHandler::check_status(&response, output)?;
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or_else(|| ValidateError::HttpStatus {
+                url: output.to_string(),
+                status: response.status().as_u16(),
+            })?;
+        Ok(reqwest::Url::parse(output)
+            .unwrap()
+            .join(location)
+            .unwrap()
+            .to_string())
+    }
+
+    async fn tus_offset(
+        client: &Client,
+        location: &str,
+        parsed_address: &ParsedAddress,
+    ) -> Option<u64> {
+        let response = client
+            .head(location)
+            .header(TUS_RESUMABLE, TUS_VERSION)
+            .header(
+                reqwest::header::USER_AGENT,
+                reqwest::header::HeaderValue::from_static(CLIENT_ID),
+            )
+            .basic_auth(&parsed_address.username, Some(&parsed_address.password))
+            .send()
+            .await
+            .ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+        response
+            .headers()
+            .get("Upload-Offset")?
+            .to_str()
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn tus_state_key(input: &str, total_size: u64, output: &str) -> String {
+        let input = std::fs::canonicalize(input)
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|_| input.to_string());
+        format!("{input}|{total_size}|{output}")
+    }
+
+    async fn put_multipart(
+        input: &str,
+        output: &str,
+        mut bar: WrappedBar,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        let parsed_address = ParsedAddress::parse_address(output, bar.silent);
+        let mut fields: Vec<(String, FormValue)> = options
+            .form
+            .iter()
+            .map(|field| HTTPSHandler::parse_form_field(field))
+            .collect();
+        if !fields.iter().any(|(_, v)| matches!(v, FormValue::File(_))) {
+            fields.push(("file".to_string(), FormValue::File(input.to_string())));
+        }
+
+        let mut total_size = 0;
+        for (_, value) in fields.iter() {
+            if let FormValue::File(path) = value {
+                total_size += std::fs::metadata(path)
+                    .unwrap_or_else(|_| panic!("Cannot determine size of form file {path}"))
+                    .len();
+            }
+        }
         bar.set_length(total_size);
+        let bar = Arc::new(bar);
 
-        let async_stream = async_stream::stream! {
+        let mut form = Form::new();
+        let mut offset = 0;
+        for (name, value) in fields {
+            form = match value {
+                FormValue::Text(text) => form.text(name, text),
+                FormValue::File(path) => {
+                    let file = tokio::fs::File::open(&path)
+                        .await
+                        .unwrap_or_else(|_| panic!("Cannot open form file {path}"));
+                    let size = file
+                        .metadata()
+                        .await
+                        .expect("Cannot determine form file size")
+                        .len();
+                    let async_stream = HTTPSHandler::progress_stream(
+                        file,
+                        offset,
+                        Some(total_size),
+                        bar.clone(),
+                        path.clone(),
+                        output.to_string(),
+                        options.timeouts,
+                    );
+                    offset += size;
+                    let part =
+                        Part::stream_with_length(reqwest::Body::wrap_stream(async_stream), size)
+                            .file_name(
+                                std::path::Path::new(&path)
+                                    .file_name()
+                                    .unwrap()
+                                    .to_string_lossy()
+                                    .to_string(),
+                            )
+                            .mime_str(&HTTPSHandler::content_type(&path))
+                            .unwrap();
+                    form.part(name, part)
+                }
+            };
+        }
+
+        let response = HTTPSHandler::client(options)
+            .request(HTTPSHandler::method(options, Method::POST), output)
+            .header(
+                reqwest::header::USER_AGENT,
+                reqwest::header::HeaderValue::from_static(CLIENT_ID),
+            )
+            .basic_auth(parsed_address.username, Some(parsed_address.password))
+            .multipart(form)
+            .send()
+            .await;
+        let response = HTTPSHandler::check_send(response, "Failed to POST form")?;
+        HTTPSHandler::print_response(response, output, options.silent).await
+    }
+
+    fn progress_stream<R: AsyncRead + Send + Unpin + 'static>(
+        file: R,
+        offset: u64,
+        total_size: Option<u64>,
+        bar: Arc<WrappedBar>,
+        input: String,
+        output: String,
+        timeouts: Timeouts,
+    ) -> impl Stream<Item = Result<Bytes, Error>> {
+        let mut reader_stream = ReaderStream::new(file);
+        let mut uploaded = offset;
+        let mut guard = SpeedGuard::new(&timeouts);
+        async_stream::stream! {
             while let Some(chunk) = reader_stream.next().await {
                 if let Ok(chunk) = &chunk {
-                    let new = min(uploaded + (chunk.len() as u64), total_size);
-                    uploaded = new;
-                    bar.set_position(new);
-                    if uploaded >= total_size {
-                        bar.finish_upload(&input_, &output_);
+                    if let Err(e) = guard.update(chunk.len() as u64) {
+                        yield Err(e.into());
+                        break;
+                    }
+                    let new = uploaded + (chunk.len() as u64);
+                    uploaded = total_size.map_or(new, |total_size| min(new, total_size));
+                    bar.set_position(uploaded);
+                    if total_size.is_some_and(|total_size| uploaded >= total_size) {
+                        bar.finish_upload(&input, &output);
                     }
                 }
                 yield chunk;
             }
+            if total_size.is_none() {
+                bar.finish_upload(&input, &output);
+            }
+        }
+    }
+
+    fn parse_form_field(field: &str) -> (String, FormValue) {
+        let (name, value) = field
+            .split_once('=')
+            .unwrap_or_else(|| panic!("Invalid form field, expected name=value: {field}"));
+        let value = match value.strip_prefix('@') {
+            Some(path) => FormValue::File(path.to_string()),
+            None => FormValue::Text(value.to_string()),
         };
+        (name.to_string(), value)
+    }
+
+    fn method(options: &Options, default: Method) -> Method {
+        match options.method.is_empty() {
+            true => default,
+            false => Method::from_bytes(options.method.to_uppercase().as_bytes())
+                .unwrap_or_else(|_| panic!("Invalid HTTP method: {}", options.method)),
+        }
+    }
+
+    fn content_type(path: &str) -> String {
+        mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .essence_str()
+            .to_string()
+    }
 
-        let client = Client::builder()
-            .redirect(if no_follow_redirects {
+    pub fn client(options: &Options) -> Client {
+        let mut builder = Client::builder()
+            .redirect(if options.no_follow_redirects {
                 reqwest::redirect::Policy::none()
             } else {
                 reqwest::redirect::Policy::default()
             })
+            .gzip(options.compressed)
+            .brotli(options.compressed)
+            .zstd(options.compressed);
+        if let Some(connect) = options.timeouts.connect {
+            builder = builder.connect_timeout(connect);
+        }
+        if let Some(read) = options.timeouts.read {
+            builder = builder.read_timeout(read);
+        }
+        if let Some(pem) = ca_bundle(options) {
+            let certificates = Certificate::from_pem_bundle(&pem)
+                .unwrap_or_else(|e| panic!("Cannot parse CA certificates {}: {e}", options.cacert));
+            builder = builder.tls_certs_merge(certificates);
+        }
+        builder
+            .tls_danger_accept_invalid_certs(options.insecure)
             .build()
-            .unwrap();
+            .unwrap()
+    }
 
-        let response = client
-            .put(output)
-            .header("content-type", "application/octet-stream")
-            .header(
-                "Range",
-                "bytes=".to_owned() + &uploaded.to_string()[..] + "-",
-            )
-            .header(
-                reqwest::header::USER_AGENT,
-                reqwest::header::HeaderValue::from_static(CLIENT_ID),
-            )
-            .basic_auth(parsed_address.username, Some(parsed_address.password))
-            .body(reqwest::Body::wrap_stream(async_stream))
-            .send()
-            .await
-            .unwrap();
-        println!("{:?}", response.text().await.unwrap());
+    fn check_send(
+        result: Result<Response, reqwest::Error>,
+        message: &str,
+    ) -> Result<Response, TimeoutError> {
+        match result {
+            Ok(response) => Ok(response),
+            Err(e) => match HTTPSHandler::timeout_error(&e) {
+                Some(timeout) => Err(timeout),
+                None => panic!("{message}: {e}"),
+            },
+        }
+    }
+
+    async fn print_response(
+        response: Response,
+        url: &str,
+        silent: bool,
+    ) -> Result<(), ValidateError> {
+        let text = response.text().await.map_err(|e| ValidateError::Response {
+            url: url.to_string(),
+            reason: e.to_string(),
+        })?;
+        if !silent && !text.is_empty() {
+            println!("{text}");
+        }
         Ok(())
     }
 
+    fn check_status(response: &Response, url: &str) -> Result<(), ValidateError> {
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(ValidateError::HttpStatus {
+                url: url.to_string(),
+                status: response.status().as_u16(),
+            }),
+        }
+    }
+
+    fn timeout_error(error: &reqwest::Error) -> Option<TimeoutError> {
+        if error.is_connect() {
+            return error.is_timeout().then_some(TimeoutError::Connect);
+        }
+        let mut source = std::error::Error::source(error);
+        while let Some(e) = source {
+            if let Some(timeout) = e.downcast_ref::<Error>().and_then(timeout_from_io) {
+                return Some(timeout);
+            }
+            source = e.source();
+        }
+        error.is_timeout().then_some(TimeoutError::Read)
+    }
+
     pub async fn get_links(input: String) -> Result<Vec<String>, Error> {
         let mut result = Vec::new();
         let res = HTTPSHandler::list(&input).await.unwrap();
@@ -129,42 +573,51 @@ impl HTTPSHandler {
         Ok(res)
     }
 
-    async fn _get(input: &str, output: &str, bar: &mut WrappedBar, no_follow_redirects: bool) -> Result<(), ValidateError> {
+    async fn _get(
+        input: &str,
+        output: &str,
+        bar: &mut WrappedBar,
+        sink: Option<io::ChannelWriter>,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
         let parsed_address = ParsedAddress::parse_address(input, bar.silent);
-        let (mut out, mut downloaded) = io::get_output(output, bar.silent);
-
-        let client = Client::builder()
-            .redirect(if no_follow_redirects {
-                reqwest::redirect::Policy::none()
-            } else {
-                reqwest::redirect::Policy::default()
-            })
-            .build()
-            .unwrap();
+        let (mut out, mut downloaded) = io::open_output(input, output, bar.silent, options, sink);
 
-        let res = client
-            .get(input)
-            .header(
+        let mut request = HTTPSHandler::client(options).get(input);
+        // Decoded bytes cannot be mapped back to a range of the encoded stream.
+        if !options.compressed && !options.decompress {
+            request = request.header(
                 "Range",
                 "bytes=".to_owned() + &downloaded.to_string()[..] + "-",
-            )
+            );
+        }
+        let res = request
             .header(
                 reqwest::header::USER_AGENT,
                 reqwest::header::HeaderValue::from_static(CLIENT_ID),
             )
             .basic_auth(parsed_address.username, Some(parsed_address.password))
             .send()
-            .await
-            .map_err(|_| format!("Failed to GET from {} to {}", &input, &output))
-            .unwrap();
+            .await;
+        let res =
+            HTTPSHandler::check_send(res, &format!("Failed to GET from {input} to {output}"))?;
         let total_size = downloaded + res.content_length().unwrap_or(0);
 
         bar.set_length(total_size);
 
         let mut stream = res.bytes_stream();
-        while let Some(item) = stream.next().await {
-            let chunk = item.map_err(|_| "Error while downloading.").unwrap();
+        let mut guard = SpeedGuard::new(&options.timeouts);
+        while let Some(item) = options.timeouts.read(stream.next()).await? {
+            let chunk = match item {
+                Ok(chunk) => chunk,
+                Err(e) => match HTTPSHandler::timeout_error(&e) {
+                    Some(timeout) => return Err(timeout.into()),
+                    None => panic!("Error while downloading."),
+                },
+            };
+            guard.update(chunk.len() as u64)?;
             out.write_all(&chunk)
+                .await
                 .map_err(|_| "Error while writing to output.")
                 .unwrap();
             let new = min(downloaded + (chunk.len() as u64), total_size);
@@ -176,19 +629,68 @@ impl HTTPSHandler {
         Ok(())
     }
 
-    async fn get_already_uploaded(output: &str, silent: bool, no_follow_redirects: bool) -> u64 {
-        let parsed_address = ParsedAddress::parse_address(output, silent);
-        let client = Client::builder()
-            .redirect(if no_follow_redirects {
-                reqwest::redirect::Policy::none()
-            } else {
-                reqwest::redirect::Policy::default()
-            })
-            .build()
-            .unwrap();
+    pub async fn stat(
+        input: &str,
+        options: &Options,
+    ) -> Result<Option<RemoteMetadata>, ValidateError> {
+        let parsed_address = ParsedAddress::parse_address(input, true);
+        let response = HTTPSHandler::client(options)
+            .head(input)
+            .header(ACCEPT_ENCODING, "identity")
+            .header(
+                reqwest::header::USER_AGENT,
+                reqwest::header::HeaderValue::from_static(CLIENT_ID),
+            )
+            .basic_auth(parsed_address.username, Some(parsed_address.password))
+            .send()
+            .await;
+        let response = HTTPSHandler::check_send(response, &format!("Failed to HEAD {input}"))?;
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        Ok(Some(RemoteMetadata {
+            size: header(CONTENT_LENGTH).and_then(|value| value.parse().ok()),
+            modified: header(LAST_MODIFIED).and_then(parse_http_date),
+            etag: header(ETAG).map(|value| value.to_string()),
+            ..Default::default()
+        }))
+    }
+
+    pub async fn is_modified(
+        input: &str,
+        etag: Option<&str>,
+        modified: Option<SystemTime>,
+        options: &Options,
+    ) -> Result<bool, ValidateError> {
+        let parsed_address = ParsedAddress::parse_address(input, true);
+        let mut request = HTTPSHandler::client(options)
+            .head(input)
+            .header(
+                reqwest::header::USER_AGENT,
+                reqwest::header::HeaderValue::from_static(CLIENT_ID),
+            )
+            .basic_auth(parsed_address.username, Some(parsed_address.password));
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(modified) = modified {
+            request = request.header(IF_MODIFIED_SINCE, format_http_date(modified));
+        }
+        let response =
+            HTTPSHandler::check_send(request.send().await, &format!("Failed to HEAD {input}"))?;
+        Ok(response.status() != StatusCode::NOT_MODIFIED)
+    }
 
-        let res = client
-            .get(output)
+    pub async fn get_filename(input: &str, options: &Options) -> Option<String> {
+        let parsed_address = ParsedAddress::parse_address(input, true);
+        let response = HTTPSHandler::client(options)
+            .head(input)
             .header(
                 reqwest::header::USER_AGENT,
                 reqwest::header::HeaderValue::from_static(CLIENT_ID),
@@ -196,9 +698,36 @@ impl HTTPSHandler {
             .basic_auth(parsed_address.username, Some(parsed_address.password))
             .send()
             .await
-            .map_err(|_| format!("Failed to GET already uploaded size from {}", &output))
+            .ok()?;
+        response
+            .headers()
+            .get(CONTENT_DISPOSITION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Slicer::filename_from_content_disposition)
+            .or_else(|| Slicer::filename_from_url(response.url().as_str()))
+    }
+
+    async fn get_already_uploaded(output: &str, silent: bool, options: &Options) -> u64 {
+        let parsed_address = ParsedAddress::parse_address(output, silent);
+        let res = HTTPSHandler::client(options)
+            .head(output)
+            .header(
+                reqwest::header::USER_AGENT,
+                reqwest::header::HeaderValue::from_static(CLIENT_ID),
+            )
+            .basic_auth(parsed_address.username, Some(parsed_address.password))
+            .send()
+            .await
+            .map_err(|_| format!("Failed to HEAD already uploaded size from {}", &output))
             .unwrap();
-        res.content_length().unwrap_or(0)
+        if !res.status().is_success() {
+            return 0;
+        }
+        res.headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0)
     }
 }
 
@@ -207,7 +736,7 @@ async fn get_https_works() {
     let expected_hash = "0e0f0d7139c8c7e3ff20cb243e94bc5993517d88e8be8d59129730607d5c631b";
     let out_file = "tokei-x86_64-unknown-linux-gnu.tar.gz";
 
-    let result = HTTPSHandler::get("https://github.com/XAMPPRocky/tokei/releases/download/v12.0.4/tokei-x86_64-unknown-linux-gnu.tar.gz", out_file, &mut WrappedBar::new_empty(), expected_hash, false).await;
+    let result = HTTPSHandler::get("https://github.com/XAMPPRocky/tokei/releases/download/v12.0.4/tokei-x86_64-unknown-linux-gnu.tar.gz", out_file, &mut WrappedBar::new_empty(), expected_hash, None, &Options::default()).await;
 
     assert!(result.is_ok());
     std::fs::remove_file(out_file).unwrap();
@@ -223,7 +752,7 @@ async fn get_resume_works() {
     )
     .unwrap();
 
-    let _ = HTTPSHandler::get("https://github.com/Byron/dua-cli/releases/download/v2.10.2/dua-v2.10.2-x86_64-unknown-linux-musl.tar.gz", out_file, &mut WrappedBar::new_empty_verbose(), "", false).await;
+    let _ = HTTPSHandler::get("https://github.com/Byron/dua-cli/releases/download/v2.10.2/dua-v2.10.2-x86_64-unknown-linux-musl.tar.gz", out_file, &mut WrappedBar::new_empty_verbose(), "", None, &Options::default()).await;
 
     let actual_size = std::fs::metadata(out_file).unwrap().len();
     assert_eq!(actual_size, expected_size);
@@ -255,44 +784,57 @@ async fn get_links_works_when_typical() {
 #[tokio::test]
 async fn test_redirect_following_enabled_by_default() {
     let out_file = "test_redirect_following.txt";
-    
+
     let result = HTTPSHandler::get(
         "http://httpbin.org/redirect/1",
         out_file,
         &mut WrappedBar::new_empty(),
         "",
-        false,
+        None,
+        &Options::default(),
     )
     .await;
 
     assert!(result.is_ok(), "Should successfully follow redirect");
-    
+
     let content = std::fs::read_to_string(out_file).unwrap();
-    assert!(content.contains("\"url\": \"http://httpbin.org/get\""), 
-            "Should have followed redirect to /get endpoint");
-    
+    assert!(
+        content.contains("\"url\": \"http://httpbin.org/get\""),
+        "Should have followed redirect to /get endpoint"
+    );
+
     std::fs::remove_file(out_file).unwrap();
 }
 
 #[tokio::test]
 async fn test_redirect_following_can_be_disabled() {
     let out_file = "test_no_redirect.txt";
-    
+
     let result = HTTPSHandler::get(
         "http://httpbin.org/redirect/1",
         out_file,
         &mut WrappedBar::new_empty(),
         "",
-        true,
+        None,
+        &Options {
+            no_follow_redirects: true,
+            ..Default::default()
+        },
     )
     .await;
 
-    assert!(result.is_ok(), "Should complete request without following redirect");
-    
-    let metadata = std::fs::metadata(out_file);
-    if metadata.is_ok() {
-        let size = metadata.unwrap().len();
-        assert!(size < 1000, "File should be small without following redirect, got {} bytes", size);
+    assert!(
+        result.is_ok(),
+        "Should complete request without following redirect"
+    );
+
+    if let Ok(metadata) = std::fs::metadata(out_file) {
+        let size = metadata.len();
+        assert!(
+            size < 1000,
+            "File should be small without following redirect, got {} bytes",
+            size
+        );
         std::fs::remove_file(out_file).unwrap();
     }
 }
@@ -300,21 +842,642 @@ async fn test_redirect_following_can_be_disabled() {
 #[tokio::test]
 async fn test_http_to_https_redirect() {
     let out_file = "test_http_to_https_redirect.html";
-    
+
     let result = HTTPSHandler::get(
         "http://github.com",
         out_file,
         &mut WrappedBar::new_empty(),
         "",
-        false,
+        None,
+        &Options::default(),
     )
     .await;
 
-    assert!(result.is_ok(), "Should successfully follow http->https redirect");
-    
+    assert!(
+        result.is_ok(),
+        "Should successfully follow http->https redirect"
+    );
+
     let content = std::fs::read_to_string(out_file).unwrap();
-    assert!(content.len() > 5000, "Should have downloaded the GitHub homepage");
+    assert!(
+        content.len() > 5000,
+        "Should have downloaded the GitHub homepage"
+    );
     assert!(content.contains("GitHub"), "Content should be from GitHub");
-    
+
     std::fs::remove_file(out_file).unwrap();
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_parse_form_field_works_when_text() {
+    let (name, value) = HTTPSHandler::parse_form_field("description=nightly build");
+    assert_eq!(name, "description");
+    assert!(matches!(value, FormValue::Text(v) if v == "nightly build"));
+}
+
+#[test]
+fn test_parse_form_field_works_when_file() {
+    let (name, value) = HTTPSHandler::parse_form_field("upload=@test/https/test.file");
+    assert_eq!(name, "upload");
+    assert!(matches!(value, FormValue::File(v) if v == "test/https/test.file"));
+}
+
+#[test]
+#[should_panic]
+fn test_parse_form_field_panics_when_no_value() {
+    HTTPSHandler::parse_form_field("description");
+}
+
+#[test]
+fn test_method_works_when_default() {
+    assert_eq!(
+        HTTPSHandler::method(&Options::default(), Method::PUT),
+        Method::PUT
+    );
+}
+
+#[test]
+fn test_method_works_when_overridden() {
+    let options = Options {
+        method: "post".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(HTTPSHandler::method(&options, Method::PUT), Method::POST);
+}
+
+#[test]
+fn test_content_type_works_when_typical() {
+    assert_eq!(
+        HTTPSHandler::content_type("archive.tar.gz"),
+        "application/gzip"
+    );
+    assert_eq!(
+        HTTPSHandler::content_type("no_extension"),
+        "application/octet-stream"
+    );
+}
+
+#[tokio::test]
+async fn test_put_multipart_works_when_typical() {
+    use crate::mock_http;
+    let (address, server) = mock_http::serve(vec![mock_http::response("200 OK", &[], b"")]).await;
+
+    let result = HTTPSHandler::put(
+        "LICENCE.md",
+        &(address + "/upload"),
+        WrappedBar::new_empty(),
+        None,
+        &Options {
+            form: vec!["description=licence".to_string()],
+            ..Default::default()
+        },
+    )
+    .await;
+
+    assert!(result.is_ok());
+    let requests = server.await.unwrap();
+    let body = String::from_utf8_lossy(&requests[0].body);
+    assert!(requests[0].head.starts_with("POST /upload"));
+    assert!(requests[0]
+        .header("content-type")
+        .unwrap()
+        .starts_with("multipart/form-data"));
+    assert!(body.contains("name=\"description\"\r\n\r\nlicence"));
+    assert!(body.contains("name=\"file\"; filename=\"LICENCE.md\""));
+    assert!(body.contains("Content-Type: text/markdown"));
+    assert!(body.contains(&std::fs::read_to_string("LICENCE.md").unwrap()));
+}
+
+#[tokio::test]
+async fn test_put_content_range_works_when_partially_uploaded() {
+    use crate::mock_http;
+    let expected = std::fs::read("LICENCE.md").unwrap();
+    let (address, server) = mock_http::serve(vec![
+        mock_http::response("200 OK", &[("Content-Length", "4")], b""),
+        mock_http::response("204 No Content", &[], b""),
+    ])
+    .await;
+
+    let result = HTTPSHandler::put(
+        "LICENCE.md",
+        &(address + "/LICENCE.md"),
+        WrappedBar::new_empty(),
+        None,
+        &Options {
+            upload_resume: "content-range".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    assert!(result.is_ok());
+    let requests = server.await.unwrap();
+    assert!(requests[0].head.starts_with("HEAD /LICENCE.md"));
+    assert!(requests[1].head.starts_with("PUT /LICENCE.md"));
+    assert_eq!(
+        requests[1].header("content-range").unwrap(),
+        format!("bytes 4-{}/{}", expected.len() - 1, expected.len())
+    );
+    assert_eq!(requests[1].body, expected[4..]);
+}
+
+#[tokio::test]
+async fn test_put_content_range_uploads_everything_when_nothing_uploaded() {
+    use crate::mock_http;
+    let expected = std::fs::read("LICENCE.md").unwrap();
+    let (address, server) = mock_http::serve(vec![
+        mock_http::response("404 Not Found", &[], b""),
+        mock_http::response("201 Created", &[], b""),
+    ])
+    .await;
+
+    let result = HTTPSHandler::put(
+        "LICENCE.md",
+        &(address + "/LICENCE.md"),
+        WrappedBar::new_empty(),
+        None,
+        &Options {
+            upload_resume: "content-range".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    assert!(result.is_ok());
+    let requests = server.await.unwrap();
+    assert!(requests[1].header("content-range").is_none());
+    assert_eq!(requests[1].body, expected);
+}
+
+#[tokio::test]
+async fn test_put_content_range_skips_upload_when_already_complete() {
+    use crate::mock_http;
+    let size = std::fs::metadata("LICENCE.md").unwrap().len().to_string();
+    let (address, server) = mock_http::serve(vec![mock_http::response(
+        "200 OK",
+        &[("Content-Length", &size)],
+        b"",
+    )])
+    .await;
+
+    let result = HTTPSHandler::put(
+        "LICENCE.md",
+        &(address + "/LICENCE.md"),
+        WrappedBar::new_empty(),
+        None,
+        &Options {
+            upload_resume: "content-range".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    assert!(result.is_ok());
+    let requests = server.await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].head.starts_with("HEAD /LICENCE.md"));
+}
+
+#[tokio::test]
+async fn test_put_uploads_everything_without_head_when_default() {
+    use crate::mock_http;
+    let expected = std::fs::read("LICENCE.md").unwrap();
+    let (address, server) =
+        mock_http::serve(vec![mock_http::response("201 Created", &[], b"")]).await;
+
+    let result = HTTPSHandler::put(
+        "LICENCE.md",
+        &(address + "/LICENCE.md"),
+        WrappedBar::new_empty(),
+        None,
+        &Options::default(),
+    )
+    .await;
+
+    assert!(result.is_ok());
+    let requests = server.await.unwrap();
+    assert!(requests[0].head.starts_with("PUT /LICENCE.md"));
+    assert!(requests[0].header("content-range").is_none());
+    assert_eq!(requests[0].body, expected);
+}
+
+#[tokio::test]
+async fn test_put_tus_works_when_typical() {
+    use crate::mock_http;
+    let expected = std::fs::read("LICENCE.md").unwrap();
+    let (address, server) = mock_http::serve(vec![
+        mock_http::response("201 Created", &[("Location", "/files/42")], b""),
+        mock_http::response("204 No Content", &[("Upload-Offset", "1073")], b""),
+    ])
+    .await;
+    let output = address + "/files/";
+
+    let result = HTTPSHandler::put(
+        "LICENCE.md",
+        &output,
+        WrappedBar::new_empty(),
+        None,
+        &Options {
+            upload_resume: "tus".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    assert!(result.is_ok());
+    let requests = server.await.unwrap();
+    assert!(requests[0].head.starts_with("POST /files/"));
+    assert_eq!(requests[0].header("tus-resumable").unwrap(), TUS_VERSION);
+    assert_eq!(
+        requests[0].header("upload-length").unwrap(),
+        expected.len().to_string()
+    );
+    assert_eq!(
+        requests[0].header("upload-metadata").unwrap(),
+        "filename TElDRU5DRS5tZA=="
+    );
+    assert!(requests[1].head.starts_with("PATCH /files/42"));
+    assert_eq!(requests[1].header("upload-offset").unwrap(), "0");
+    assert_eq!(requests[1].body, expected);
+    let state_key = HTTPSHandler::tus_state_key("LICENCE.md", expected.len() as u64, &output);
+    assert!(State::load(TUS_STATE, &state_key).is_none());
+}
+
+#[tokio::test]
+async fn test_put_tus_fails_when_creation_rejected() {
+    use crate::mock_http;
+    let (address, server) =
+        mock_http::serve(vec![mock_http::response("403 Forbidden", &[], b"")]).await;
+    let output = address + "/rejected/";
+
+    let result = HTTPSHandler::put(
+        "LICENCE.md",
+        &output,
+        WrappedBar::new_empty(),
+        None,
+        &Options {
+            upload_resume: "tus".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    server.await.unwrap();
+    assert!(matches!(
+        result,
+        Err(ValidateError::HttpStatus { status: 403, .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_put_tus_fails_when_location_missing() {
+    use crate::mock_http;
+    let (address, server) =
+        mock_http::serve(vec![mock_http::response("201 Created", &[], b"")]).await;
+    let output = address + "/no-location/";
+
+    let result = HTTPSHandler::put(
+        "LICENCE.md",
+        &output,
+        WrappedBar::new_empty(),
+        None,
+        &Options {
+            upload_resume: "tus".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    server.await.unwrap();
+    assert!(matches!(
+        result,
+        Err(ValidateError::HttpStatus { status: 201, .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_put_tus_fails_when_input_not_seekable() {
+    let result = HTTPSHandler::put(
+        "-",
+        "http://127.0.0.1:1/files/",
+        WrappedBar::new_empty(),
+        None,
+        &Options {
+            upload_resume: "tus".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    assert!(matches!(result, Err(ValidateError::Unsupported { .. })));
+}
+
+#[tokio::test]
+async fn test_put_tus_resumes_when_upload_location_known() {
+    use crate::mock_http;
+    let expected = std::fs::read("LICENCE.md").unwrap();
+    let (address, server) = mock_http::serve(vec![
+        mock_http::response("200 OK", &[("Upload-Offset", "4")], b""),
+        mock_http::response("204 No Content", &[], b""),
+    ])
+    .await;
+    let output = address.clone() + "/files/";
+    let state_key = HTTPSHandler::tus_state_key("LICENCE.md", expected.len() as u64, &output);
+    State::save(
+        TUS_STATE,
+        &state_key,
+        &HashMap::from([("location".to_string(), address + "/files/42")]),
+    );
+
+    let result = HTTPSHandler::put(
+        "LICENCE.md",
+        &output,
+        WrappedBar::new_empty(),
+        None,
+        &Options {
+            upload_resume: "tus".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    assert!(result.is_ok());
+    let requests = server.await.unwrap();
+    assert!(requests[0].head.starts_with("HEAD /files/42"));
+    assert!(requests[1].head.starts_with("PATCH /files/42"));
+    assert_eq!(requests[1].header("upload-offset").unwrap(), "4");
+    assert_eq!(requests[1].body, expected[4..]);
+    assert!(State::load(TUS_STATE, &state_key).is_none());
+}
+
+#[tokio::test]
+async fn test_get_times_out_when_server_is_silent() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = format!("http://{}/file", listener.local_addr().unwrap());
+    let _handle = tokio::spawn(async move {
+        let (_socket, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+    });
+    let out_file = "test_get_times_out_when_server_is_silent";
+    let options = Options {
+        timeouts: Timeouts {
+            read: Some(std::time::Duration::from_millis(100)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut bar = WrappedBar::new_empty();
+
+    let result = HTTPSHandler::get(&address, out_file, &mut bar, "", None, &options).await;
+    let _ = std::fs::remove_file(out_file);
+
+    assert!(matches!(
+        result,
+        Err(ValidateError::Timeout {
+            source: TimeoutError::Read
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_get_aborts_when_too_slow() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = format!("http://{}/file", listener.local_addr().unwrap());
+    let _handle = tokio::spawn(async move {
+        use tokio::io::AsyncWriteExt;
+        let (mut socket, _) = listener.accept().await.unwrap();
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n")
+            .await
+            .unwrap();
+        loop {
+            if socket.write_all(b"a").await.is_err() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+    });
+    let out_file = "test_get_aborts_when_too_slow";
+    let options = Options {
+        timeouts: Timeouts {
+            speed_limit: Some(1000),
+            speed_time: Some(std::time::Duration::from_millis(200)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut bar = WrappedBar::new_empty();
+
+    let result = HTTPSHandler::get(&address, out_file, &mut bar, "", None, &options).await;
+    let _ = std::fs::remove_file(out_file);
+
+    assert!(matches!(
+        result,
+        Err(ValidateError::Timeout {
+            source: TimeoutError::LowSpeed { .. }
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_get_filename_works_when_content_disposition() {
+    let (address, server) = crate::mock_http::serve(vec![crate::mock_http::response(
+        "200 OK",
+        &[(
+            "Content-Disposition",
+            "attachment; filename*=UTF-8''release%20notes.txt",
+        )],
+        b"",
+    )])
+    .await;
+
+    let filename =
+        HTTPSHandler::get_filename(&(address + "/download?id=42"), &Options::default()).await;
+    server.await.unwrap();
+
+    assert_eq!(filename, Some("release notes.txt".to_string()));
+}
+
+#[tokio::test]
+async fn test_get_filename_uses_redirected_url_when_no_content_disposition() {
+    let (redirected, redirected_server) =
+        crate::mock_http::serve(vec![crate::mock_http::response("200 OK", &[], b"")]).await;
+    let location = redirected + "/files/asset.tar.gz?X-Amz-Signature=abc";
+    let (address, server) = crate::mock_http::serve(vec![crate::mock_http::response(
+        "302 Found",
+        &[("Location", &location)],
+        b"",
+    )])
+    .await;
+
+    let filename =
+        HTTPSHandler::get_filename(&(address + "/download?id=42"), &Options::default()).await;
+    server.await.unwrap();
+    redirected_server.await.unwrap();
+
+    assert_eq!(filename, Some("asset.tar.gz".to_string()));
+}
+
+#[tokio::test]
+async fn test_stat_works_when_typical() {
+    let (address, server) = crate::mock_http::serve(vec![crate::mock_http::response(
+        "200 OK",
+        &[("Content-Length", "42")],
+        b"",
+    )])
+    .await;
+
+    let metadata = HTTPSHandler::stat(&(address + "/file"), &Options::default()).await;
+    server.await.unwrap();
+
+    assert_eq!(
+        metadata.unwrap(),
+        Some(RemoteMetadata {
+            size: Some(42),
+            ..Default::default()
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_stat_is_none_when_not_found() {
+    let (address, server) =
+        crate::mock_http::serve(vec![crate::mock_http::response("404 Not Found", &[], b"")]).await;
+
+    let metadata = HTTPSHandler::stat(&(address + "/file"), &Options::default()).await;
+    server.await.unwrap();
+
+    assert_eq!(metadata.unwrap(), None);
+}
+
+#[test]
+fn test_http_date_roundtrip_works_when_typical() {
+    let date = "Wed, 21 Oct 2015 07:28:00 GMT";
+    assert_eq!(format_http_date(parse_http_date(date).unwrap()), date);
+}
+
+#[tokio::test]
+async fn test_is_modified_is_false_when_not_modified() {
+    let (address, server) = crate::mock_http::serve(vec![crate::mock_http::response(
+        "304 Not Modified",
+        &[],
+        b"",
+    )])
+    .await;
+
+    let is_modified = HTTPSHandler::is_modified(
+        &(address + "/file"),
+        Some("\"abc\""),
+        parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT"),
+        &Options::default(),
+    )
+    .await;
+    let requests = server.await.unwrap();
+
+    assert!(!is_modified.unwrap());
+    assert_eq!(
+        requests[0].header("if-none-match"),
+        Some("\"abc\"".to_string())
+    );
+    assert_eq!(
+        requests[0].header("if-modified-since"),
+        Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string())
+    );
+}
+
+#[cfg(test)]
+fn gzip(content: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+    let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(content).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[tokio::test]
+async fn test_get_decompresses_when_decompress() {
+    use sha2::{Digest, Sha256};
+    let content = "hello aim\n".repeat(100);
+    let compressed = gzip(content.as_bytes());
+    let expected_sha256 = format!("{:x}", Sha256::digest(&compressed));
+    let (address, _server) =
+        crate::mock_http::serve(vec![crate::mock_http::response("200 OK", &[], &compressed)]).await;
+    let out_file = "test_get_decompresses_when_decompress";
+    let options = Options {
+        decompress: true,
+        ..Default::default()
+    };
+    let mut bar = WrappedBar::new_empty();
+
+    let result = HTTPSHandler::get(
+        &(address + "/file.txt.gz"),
+        out_file,
+        &mut bar,
+        &expected_sha256,
+        None,
+        &options,
+    )
+    .await;
+    let actual = std::fs::read_to_string(out_file).unwrap();
+    std::fs::remove_file(out_file).unwrap();
+
+    assert!(result.is_ok());
+    assert_eq!(actual, content);
+}
+
+#[tokio::test]
+async fn test_get_decodes_content_encoding_when_compressed() {
+    let content = "hello aim\n".repeat(100);
+    let (address, server) = crate::mock_http::serve(vec![crate::mock_http::response(
+        "200 OK",
+        &[("Content-Encoding", "gzip")],
+        &gzip(content.as_bytes()),
+    )])
+    .await;
+    let out_file = "test_get_decodes_content_encoding_when_compressed";
+    let options = Options {
+        compressed: true,
+        ..Default::default()
+    };
+    let mut bar = WrappedBar::new_empty();
+
+    let result =
+        HTTPSHandler::get(&(address + "/file"), out_file, &mut bar, "", None, &options).await;
+    let actual = std::fs::read_to_string(out_file).unwrap();
+    std::fs::remove_file(out_file).unwrap();
+    let requests = server.await.unwrap();
+
+    assert!(result.is_ok());
+    assert_eq!(actual, content);
+    assert!(requests[0]
+        .header("accept-encoding")
+        .unwrap()
+        .contains("gzip"));
+}
+
+#[tokio::test]
+async fn test_get_restarts_when_compressed_and_output_exists() {
+    let content = "hello aim\n".repeat(100);
+    let (address, server) = crate::mock_http::serve(vec![crate::mock_http::response(
+        "200 OK",
+        &[("Content-Encoding", "gzip")],
+        &gzip(content.as_bytes()),
+    )])
+    .await;
+    let out_file = "test_get_restarts_when_compressed_and_output_exists";
+    std::fs::write(out_file, "hello").unwrap();
+    let options = Options {
+        compressed: true,
+        ..Default::default()
+    };
+    let mut bar = WrappedBar::new_empty();
+
+    let result =
+        HTTPSHandler::get(&(address + "/file"), out_file, &mut bar, "", None, &options).await;
+    let actual = std::fs::read_to_string(out_file).unwrap();
+    std::fs::remove_file(out_file).unwrap();
+    let requests = server.await.unwrap();
+
+    assert!(result.is_ok());
+    assert_eq!(actual, content);
+    assert!(requests[0].header("range").is_none());
+}
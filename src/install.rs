@@ -0,0 +1,488 @@
+use regex::Regex;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use untildify::untildify;
+
+use crate::driver::{Driver, Options};
+use crate::extract::{Archive, Extractor};
+use crate::hash::HashChecker;
+use crate::io::Compression;
+use crate::release::Release;
+use crate::slicer::Slicer;
+use crate::state::State;
+
+const DEFAULT_BIN_DIR: &str = "~/.local/bin";
+const MANIFEST: &str = "installed";
+const EXECUTABLE_MODE: u32 = 0o755;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Installed {
+    pub name: String,
+    pub version: String,
+    pub sha256: String,
+    pub source: String,
+    pub path: String,
+}
+
+impl Installed {
+    fn to_line(&self) -> String {
+        [
+            &self.name,
+            &self.version,
+            &self.sha256,
+            &self.source,
+            &self.path,
+        ]
+        .map(|field| field.as_str())
+        .join("\t")
+    }
+
+    fn from_line(line: &str) -> Option<Installed> {
+        let mut fields = line.split('\t').map(|field| field.to_string());
+        Some(Installed {
+            name: fields.next()?,
+            version: fields.next()?,
+            sha256: fields.next()?,
+            source: fields.next()?,
+            path: fields.next()?,
+        })
+    }
+}
+
+pub struct Manifest {
+    path: PathBuf,
+}
+
+impl Manifest {
+    pub fn new(path: PathBuf) -> Manifest {
+        Manifest { path }
+    }
+
+    pub fn load(&self) -> Vec<Installed> {
+        fs::read_to_string(&self.path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(Installed::from_line)
+            .collect()
+    }
+
+    pub fn record(&self, installed: &Installed) -> io::Result<()> {
+        let mut entries = self.load();
+        entries.retain(|entry| entry.name != installed.name);
+        entries.push(installed.clone());
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        self.save(&entries)
+    }
+
+    pub fn remove(&self, name: &str) -> io::Result<Option<Installed>> {
+        let mut entries = self.load();
+        let removed = entries
+            .iter()
+            .position(|entry| entry.name == name)
+            .map(|index| entries.remove(index));
+        self.save(&entries)?;
+        Ok(removed)
+    }
+
+    fn save(&self, entries: &[Installed]) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents: String = entries.iter().map(|entry| entry.to_line() + "\n").collect();
+        fs::write(&self.path, contents)
+    }
+}
+
+struct Resolved {
+    url: String,
+    filename: String,
+    version: String,
+    sha256: Option<String>,
+}
+
+pub struct Installer {
+    pub manifest: Manifest,
+    pub bin_dir: PathBuf,
+}
+
+impl Installer {
+    pub fn new(bin_dir: &str) -> Installer {
+        let bin_dir = match bin_dir.is_empty() {
+            true => env::var("AIM_BIN_DIR").unwrap_or(DEFAULT_BIN_DIR.to_string()),
+            false => bin_dir.to_string(),
+        };
+        Installer {
+            manifest: Manifest::new(State::dir().join(MANIFEST)),
+            bin_dir: PathBuf::from(untildify(&bin_dir)),
+        }
+    }
+
+    pub async fn install(
+        &self,
+        source: &str,
+        name: Option<&str>,
+        options: &Options,
+    ) -> io::Result<Installed> {
+        let resolved = Installer::resolve(source, options).await?;
+        // Shortcuts are recorded without their tag, so that upgrades follow the latest release.
+        let source = match Release::is_shortcut(source) {
+            true => source.split('@').next().unwrap_or(source),
+            false => source,
+        };
+        self.install_resolved(source, resolved, name, options).await
+    }
+
+    pub fn list(&self) -> Vec<Installed> {
+        self.manifest.load()
+    }
+
+    pub async fn upgrade(&self, name: Option<&str>, options: &Options) -> io::Result<()> {
+        let entries: Vec<Installed> = self
+            .manifest
+            .load()
+            .into_iter()
+            .filter(|entry| name.is_none_or(|name| entry.name == name))
+            .collect();
+        if let (Some(name), true) = (name, entries.is_empty()) {
+            return Err(Installer::not_installed(name));
+        }
+        for entry in entries {
+            let resolved = Installer::resolve(&entry.source, options).await?;
+            let is_versioned = Release::is_shortcut(&entry.source);
+            if is_versioned && resolved.version == entry.version {
+                if !options.silent {
+                    println!("{} {} is up to date.", entry.name, entry.version);
+                }
+                continue;
+            }
+            let installer = Installer {
+                manifest: Manifest::new(self.manifest.path.clone()),
+                bin_dir: Path::new(&entry.path)
+                    .parent()
+                    .map(|parent| parent.to_path_buf())
+                    .unwrap_or(self.bin_dir.clone()),
+            };
+            let installed = installer
+                .install_resolved(&entry.source, resolved, Some(&entry.name), options)
+                .await?;
+            if !options.silent {
+                match installed.sha256 == entry.sha256 {
+                    true => println!("{} {} is up to date.", entry.name, entry.version),
+                    false => println!(
+                        "Upgraded {} from {} to {}.",
+                        entry.name, entry.version, installed.version
+                    ),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn uninstall(&self, name: &str) -> io::Result<()> {
+        let entry = self
+            .manifest
+            .remove(name)?
+            .ok_or_else(|| Installer::not_installed(name))?;
+        match fs::remove_file(&entry.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    async fn resolve(source: &str, options: &Options) -> io::Result<Resolved> {
+        if Release::is_shortcut(source) {
+            let asset = Release::resolve(source, options).await?;
+            return Ok(Resolved {
+                url: asset.url,
+                filename: asset.name,
+                version: asset.version,
+                sha256: asset.sha256,
+            });
+        }
+        let filename = Slicer::filename_from_url(source).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Cannot derive a file name from {source}"),
+            )
+        })?;
+        Ok(Resolved {
+            url: source.to_string(),
+            version: Installer::version_from_name(&filename),
+            filename,
+            sha256: None,
+        })
+    }
+
+    async fn install_resolved(
+        &self,
+        source: &str,
+        resolved: Resolved,
+        name: Option<&str>,
+        options: &Options,
+    ) -> io::Result<Installed> {
+        let work = env::temp_dir().join(format!("aim-install-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&work)?;
+        let result = self
+            .install_in(&work, source, resolved, name, options)
+            .await;
+        let _ = fs::remove_dir_all(&work);
+        result
+    }
+
+    async fn install_in(
+        &self,
+        work: &Path,
+        source: &str,
+        resolved: Resolved,
+        name: Option<&str>,
+        options: &Options,
+    ) -> io::Result<Installed> {
+        let (download, sha256) = Installer::download(
+            work,
+            &resolved.url,
+            &resolved.filename,
+            resolved.sha256,
+            options,
+        )
+        .await?;
+        let extracted = Installer::unpack(work, &download, &resolved.filename)?;
+
+        let name = name
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| Installer::tool_name(&resolved.filename));
+        let executable = Installer::find_executable(&extracted, &name)?;
+        let target = self.place(&executable, &name)?;
+        let installed = Installed {
+            name: name.clone(),
+            version: resolved.version,
+            sha256,
+            source: source.to_string(),
+            path: target.to_string_lossy().to_string(),
+        };
+        self.manifest.record(&installed)?;
+        if !options.silent {
+            println!(
+                "Installed {name} {} to {}.",
+                installed.version, installed.path
+            );
+        }
+        Ok(installed)
+    }
+
+    pub async fn download(
+        work: &Path,
+        url: &str,
+        filename: &str,
+        sha256: Option<String>,
+        options: &Options,
+    ) -> io::Result<(String, String)> {
+        let download = work.join(filename).to_string_lossy().to_string();
+        let download_options = Options {
+            expected_sha256: match options.expected_sha256.is_empty() {
+                true => sha256.unwrap_or_default(),
+                false => options.expected_sha256.clone(),
+            },
+            on_exists: "overwrite".to_string(),
+            extract_to: String::new(),
+            ..options.clone()
+        };
+        Driver::dispatch(url, &download, &download_options).await?;
+        let sha256 = HashChecker::sha256sum(&download);
+        Ok((download, sha256))
+    }
+
+    pub fn unpack(work: &Path, download: &str, filename: &str) -> io::Result<PathBuf> {
+        let extracted = work.join("extracted");
+        match Archive::from_name(filename) {
+            Some(_) => Extractor::finish(
+                download,
+                &Options {
+                    extract_to: extracted.to_string_lossy().to_string(),
+                    ..Default::default()
+                },
+            )?,
+            None => {
+                fs::create_dir_all(&extracted)?;
+                fs::rename(download, extracted.join(filename))?;
+            }
+        }
+        Ok(extracted)
+    }
+
+    fn place(&self, executable: &Path, name: &str) -> io::Result<PathBuf> {
+        let file_name = match executable.extension().is_some_and(|e| e == "exe") {
+            true => name.to_string() + ".exe",
+            false => name.to_string(),
+        };
+        fs::create_dir_all(&self.bin_dir)?;
+        let target = self.bin_dir.join(&file_name);
+        let staging = self.bin_dir.join(format!(".{file_name}.aim-install"));
+        fs::copy(executable, &staging)?;
+        crate::io::preserve(&staging.to_string_lossy(), None, Some(EXECUTABLE_MODE))?;
+        fs::rename(&staging, &target)?;
+        Ok(target)
+    }
+
+    pub fn tool_name(filename: &str) -> String {
+        let stem = filename
+            .strip_suffix(".zip")
+            .or_else(|| filename.strip_suffix(".tgz"))
+            .unwrap_or(Compression::strip_extension(filename));
+        let stem = stem.strip_suffix(".tar").unwrap_or(stem);
+        let stem = stem.strip_suffix(".exe").unwrap_or(stem);
+        match stem.contains('-') {
+            true => Slicer::target(stem).to_string(),
+            false => stem.to_string(),
+        }
+    }
+
+    pub fn version_from_name(filename: &str) -> String {
+        Regex::new(r"v?\d+(\.\d+)+")
+            .unwrap()
+            .find(filename)
+            .map(|version| version.as_str().to_string())
+            .unwrap_or("unknown".to_string())
+    }
+
+    pub fn find_executable(dir: &Path, name: &str) -> io::Result<PathBuf> {
+        let mut files = vec![];
+        Installer::collect_files(dir, &mut files)?;
+        let candidates = [name.to_string(), name.to_string() + ".exe"];
+        if let Some(file) = files.iter().find(|file| {
+            file.file_name()
+                .is_some_and(|f| candidates.iter().any(|c| f == c.as_str()))
+        }) {
+            return Ok(file.clone());
+        }
+        let executables: Vec<&PathBuf> = files
+            .iter()
+            .filter(|file| fs::metadata(file).is_ok_and(|m| crate::io::get_mode(&m) & 0o111 != 0))
+            .collect();
+        match executables[..] {
+            [executable] => Ok(executable.clone()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Cannot find the executable {name}, choose it with --name"),
+            )),
+        }
+    }
+
+    fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                Installer::collect_files(&entry.path(), files)?;
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+        files.sort();
+        Ok(())
+    }
+
+    fn not_installed(name: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{name} was not installed by aim"),
+        )
+    }
+}
+
+#[test]
+fn test_tool_name_works_when_typical() {
+    assert_eq!(
+        Installer::tool_name("dua-v2.10.2-x86_64-unknown-linux-musl.tar.gz"),
+        "dua"
+    );
+    assert_eq!(Installer::tool_name("tokei.exe"), "tokei");
+    assert_eq!(Installer::tool_name("jq"), "jq");
+}
+
+#[test]
+fn test_version_from_name_works_when_typical() {
+    assert_eq!(
+        Installer::version_from_name("dua-v2.10.2-x86_64-unknown-linux-musl.tar.gz"),
+        "v2.10.2"
+    );
+    assert_eq!(Installer::version_from_name("jq-linux64"), "unknown");
+}
+
+#[test]
+fn test_manifest_record_and_remove_works_when_typical() {
+    let folder = "test_manifest_record_and_remove_works_when_typical";
+    let manifest = Manifest::new(PathBuf::from(folder).join(MANIFEST));
+    let installed = Installed {
+        name: "dua".to_string(),
+        version: "v2.10.2".to_string(),
+        sha256: "a".repeat(64),
+        source: "gh:Byron/dua-cli".to_string(),
+        path: "/usr/local/bin/dua".to_string(),
+    };
+
+    manifest.record(&installed).unwrap();
+    manifest.record(&installed).unwrap();
+    let loaded = manifest.load();
+    let removed = manifest.remove("dua").unwrap();
+    let remaining = manifest.load();
+    fs::remove_dir_all(folder).unwrap();
+
+    assert_eq!(loaded, vec![installed.clone()]);
+    assert_eq!(removed, Some(installed));
+    assert!(remaining.is_empty());
+}
+
+#[tokio::test]
+async fn test_install_and_uninstall_works_when_typical() {
+    use crate::mock_http::{response, serve};
+    let folder = "test_install_and_uninstall_works_when_typical";
+    let encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(7);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "tool-v1.2.0/tool", &b"#!/bin/"[..])
+        .unwrap();
+    let archive = builder.into_inner().unwrap().finish().unwrap();
+    let (address, _server) = serve(vec![response("200 OK", &[], &archive)]).await;
+    let installer = Installer {
+        manifest: Manifest::new(PathBuf::from(folder).join(MANIFEST)),
+        bin_dir: PathBuf::from(folder).join("bin"),
+    };
+    let options = Options {
+        silent: true,
+        ..Default::default()
+    };
+
+    let installed = installer
+        .install(
+            &(address + "/tool-v1.2.0-x86_64-unknown-linux-gnu.tar.gz"),
+            None,
+            &options,
+        )
+        .await
+        .unwrap();
+    let target = PathBuf::from(folder).join("bin/tool");
+    let content = fs::read_to_string(&target).unwrap();
+    let mode = crate::io::get_mode(&fs::metadata(&target).unwrap());
+    let listed = installer.list();
+    installer.uninstall("tool").unwrap();
+    let is_removed = !target.exists() && installer.list().is_empty();
+    fs::remove_dir_all(folder).unwrap();
+
+    assert_eq!(content, "#!/bin/");
+    #[cfg(unix)]
+    assert_eq!(mode, EXECUTABLE_MODE);
+    assert_eq!(installed.version, "v1.2.0");
+    assert_eq!(
+        installed.sha256,
+        format!("{:x}", <sha2::Sha256 as sha2::Digest>::digest(&archive))
+    );
+    assert_eq!(listed, vec![installed]);
+    assert!(is_removed);
+}
@@ -1,5 +1,18 @@
+use sha2::{Digest, Sha256};
+use std::cmp::min;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::Write;
+use std::io::{Read, Write};
+use std::pin::Pin;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use tokio::io::{AsyncRead, AsyncSeekExt, ReadBuf};
+use tokio::sync::mpsc;
+
+use crate::driver::Options;
+use crate::extract::Extractor;
+use crate::slicer::Slicer;
 
 fn get_output_file(path: &str, silent: bool) -> (Option<std::fs::File>, u64) {
     let mut transferred: u64 = 0;
@@ -9,12 +22,7 @@ fn get_output_file(path: &str, silent: bool) -> (Option<std::fs::File>, u64) {
             if !silent {
                 println!("File exists. Resuming.");
             }
-            file = Some(
-                std::fs::OpenOptions::new()
-                    .append(true)
-                    .open(path)
-                    .unwrap(),
-            );
+            file = Some(std::fs::OpenOptions::new().append(true).open(path).unwrap());
 
             let file_size = std::fs::metadata(path).unwrap().len();
             transferred = file_size;
@@ -42,6 +50,434 @@ pub fn get_output(path: &str, silent: bool) -> (Box<dyn Write + Send>, u64) {
     (output, transferred)
 }
 
+pub fn open_output(
+    input: &str,
+    path: &str,
+    silent: bool,
+    options: &Options,
+    sink: Option<ChannelWriter>,
+) -> (Output, u64) {
+    if let Some(writer) = sink {
+        return (Output::Channel(writer), 0);
+    }
+    if Extractor::is_streamed(path, options) {
+        let writer = Extractor::get_output(path, silent, options);
+        return (Output::Channel(hashed_channel(path, writer)), 0);
+    }
+    let (writer, transferred) = match (options.decompress, options.compressed) {
+        (true, _) => (get_decompressed_output(input, path, silent), 0),
+        (false, true) => (get_new_output(path, silent), 0),
+        (false, false) => get_output(path, silent),
+    };
+    (Output::Writer(writer), transferred)
+}
+
+pub enum Output {
+    Writer(Box<dyn Write + Send>),
+    Channel(ChannelWriter),
+}
+
+impl Output {
+    pub async fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        match self {
+            Output::Writer(writer) => writer.write_all(buf),
+            Output::Channel(writer) => writer.send(buf).await,
+        }
+    }
+
+    // Only for blocking threads, a channel then waits for the reader without an executor.
+    pub fn into_write(self) -> Box<dyn Write + Send> {
+        match self {
+            Output::Writer(writer) => writer,
+            Output::Channel(writer) => Box::new(writer),
+        }
+    }
+}
+
+fn get_new_output(path: &str, silent: bool) -> Box<dyn Write + Send> {
+    if path != "stdout" && std::path::Path::new(path).exists() {
+        std::fs::remove_file(path)
+            .map_err(|_| format!("Failed to replace file '{path}'"))
+            .unwrap();
+    }
+    get_output(path, silent).0
+}
+
+pub fn is_streamed(path: &str, options: &Options) -> bool {
+    options.decompress || Extractor::is_streamed(path, options)
+}
+
+pub fn is_stdin(path: &str) -> bool {
+    matches!(path, "-" | "stdin")
+}
+
+pub async fn open_input(path: &str, source: Option<Input>) -> std::io::Result<Input> {
+    if let Some(source) = source {
+        return Ok(source);
+    }
+    if is_stdin(path) {
+        return Ok(Input {
+            reader: InputReader::Stdin(tokio::io::stdin()),
+            size: None,
+            metadata: None,
+        });
+    }
+    let file = tokio::fs::File::open(path).await?;
+    let metadata = file.metadata().await?;
+    Ok(Input {
+        reader: InputReader::File(file),
+        size: Some(metadata.len()),
+        metadata: Some(metadata),
+    })
+}
+
+pub struct Input {
+    pub reader: InputReader,
+    pub size: Option<u64>,
+    pub metadata: Option<std::fs::Metadata>,
+}
+
+impl Input {
+    pub fn piped(reader: ChannelReader, size: Option<u64>) -> Input {
+        Input {
+            reader: InputReader::Pipe(reader),
+            size,
+            metadata: None,
+        }
+    }
+
+    pub fn is_resumable(&self) -> bool {
+        matches!(self.reader, InputReader::File(_))
+    }
+}
+
+pub enum InputReader {
+    File(tokio::fs::File),
+    Pipe(ChannelReader),
+    Stdin(tokio::io::Stdin),
+}
+
+impl InputReader {
+    pub async fn skip(&mut self, offset: u64) -> std::io::Result<()> {
+        match self {
+            InputReader::File(file) => file
+                .seek(std::io::SeekFrom::Start(offset))
+                .await
+                .map(|_| ()),
+            _ if offset == 0 => Ok(()),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Cannot resume a streamed upload",
+            )),
+        }
+    }
+
+    pub async fn into_std(self) -> Box<dyn Read + Send> {
+        match self {
+            InputReader::File(file) => Box::new(file.into_std().await),
+            InputReader::Pipe(reader) => Box::new(reader),
+            InputReader::Stdin(_) => Box::new(std::io::stdin()),
+        }
+    }
+}
+
+impl AsyncRead for InputReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            InputReader::File(file) => Pin::new(file).poll_read(cx, buf),
+            InputReader::Pipe(reader) => Pin::new(reader).poll_read(cx, buf),
+            InputReader::Stdin(stdin) => Pin::new(stdin).poll_read(cx, buf),
+        }
+    }
+}
+
+pub fn channel(capacity: usize) -> (ChannelWriter, ChannelReader) {
+    let (sender, receiver) = mpsc::channel(capacity);
+    let reader = ChannelReader {
+        receiver,
+        chunk: vec![],
+        position: 0,
+        pending: None,
+        is_closed: false,
+    };
+    let writer = ChannelWriter {
+        sender,
+        hasher: Arc::new(Mutex::new(Sha256::new())),
+    };
+    (writer, reader)
+}
+
+#[derive(Clone)]
+pub struct ChannelWriter {
+    sender: mpsc::Sender<std::io::Result<Vec<u8>>>,
+    hasher: Arc<Mutex<Sha256>>,
+}
+
+impl ChannelWriter {
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    pub async fn send(&self, buf: &[u8]) -> std::io::Result<()> {
+        self.sender
+            .send(Ok(buf.to_vec()))
+            .await
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+        self.hasher.lock().unwrap().update(buf);
+        Ok(())
+    }
+
+    pub async fn fail(self, error: std::io::Error) {
+        let _ = self.sender.send(Err(error)).await;
+    }
+
+    pub fn sha256(&self) -> String {
+        let hasher = self.hasher.lock().unwrap().clone();
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.sender
+            .blocking_send(Ok(buf.to_vec()))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+        self.hasher.lock().unwrap().update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct ChannelReader {
+    receiver: mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    position: usize,
+    pending: Option<Vec<u8>>,
+    is_closed: bool,
+}
+
+impl ChannelReader {
+    // The latest chunk is held back until the next one arrives or the writer closes,
+    // so the final bytes are only released once the writer has finished successfully.
+    fn receive(&mut self, received: Option<std::io::Result<Vec<u8>>>) -> std::io::Result<()> {
+        let next = match received {
+            Some(Ok(chunk)) => self.pending.replace(chunk),
+            Some(Err(e)) => return Err(e),
+            None => {
+                self.is_closed = true;
+                self.pending.take()
+            }
+        };
+        if let Some(chunk) = next {
+            self.chunk = chunk;
+            self.position = 0;
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, buf: &mut [u8]) -> usize {
+        let count = min(buf.len(), self.chunk.len() - self.position);
+        buf[..count].copy_from_slice(&self.chunk[self.position..self.position + count]);
+        self.position += count;
+        count
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.chunk.len() {
+            if self.is_closed {
+                return Ok(0);
+            }
+            let received = self.receiver.blocking_recv();
+            self.receive(received)?;
+        }
+        Ok(self.take(buf))
+    }
+}
+
+impl AsyncRead for ChannelReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let reader = self.get_mut();
+        while reader.position == reader.chunk.len() {
+            if reader.is_closed {
+                return Poll::Ready(Ok(()));
+            }
+            match reader.receiver.poll_recv(cx) {
+                Poll::Ready(received) => reader.receive(received)?,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let count = reader.take(buf.initialize_unfilled());
+        buf.advance(count);
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub fn get_decompressed_output(input: &str, path: &str, silent: bool) -> Box<dyn Write + Send> {
+    let out: Box<dyn Write + Send> = match path {
+        "stdout" => Box::new(std::io::stdout()),
+        _ => {
+            if !silent {
+                println!("Decompressing to new file.");
+            }
+            Box::new(
+                File::create(path)
+                    .map_err(|_| format!("Failed to create file '{path}'"))
+                    .unwrap(),
+            )
+        }
+    };
+    let out = std::io::BufWriter::new(out);
+    let decoder: Box<dyn Write + Send> = match Compression::from_name(input) {
+        Some(Compression::Gzip) => Box::new(flate2::write::MultiGzDecoder::new(out)),
+        Some(Compression::Xz) => Box::new(xz2::write::XzDecoder::new_multi_decoder(out)),
+        Some(Compression::Zstd) => Box::new(ZstdWriter(Some(
+            zstd::stream::write::Decoder::new(out).expect("Cannot create zstd decoder"),
+        ))),
+        None => panic!("Cannot decompress {input}: only .gz, .xz and .zst are supported"),
+    };
+    hashed(path, decoder)
+}
+
+pub fn hashed(path: &str, inner: Box<dyn Write + Send>) -> Box<dyn Write + Send> {
+    let hasher = Arc::new(Mutex::new(Sha256::new()));
+    COMPRESSED_HASHES
+        .lock()
+        .unwrap()
+        .insert(path.to_string(), hasher.clone());
+    Box::new(HashingWriter { inner, hasher })
+}
+
+fn hashed_channel(path: &str, writer: ChannelWriter) -> ChannelWriter {
+    COMPRESSED_HASHES
+        .lock()
+        .unwrap()
+        .insert(path.to_string(), writer.hasher.clone());
+    writer
+}
+
+pub fn take_compressed_sha256(path: &str) -> Option<String> {
+    let hasher = COMPRESSED_HASHES.lock().unwrap().remove(path)?;
+    let hasher = hasher.lock().unwrap().clone();
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    const EXTENSIONS: [(&'static str, Compression); 4] = [
+        (".gz", Compression::Gzip),
+        (".xz", Compression::Xz),
+        (".zst", Compression::Zstd),
+        (".zstd", Compression::Zstd),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Compression> {
+        let name = Slicer::filename_from_url(name).unwrap_or_default();
+        Compression::EXTENSIONS
+            .into_iter()
+            .find(|(extension, _)| name.ends_with(extension))
+            .map(|(_, compression)| compression)
+    }
+
+    pub fn strip_extension(name: &str) -> &str {
+        Compression::EXTENSIONS
+            .iter()
+            .find_map(|(extension, _)| name.strip_suffix(extension))
+            .filter(|stripped| !stripped.is_empty())
+            .unwrap_or(name)
+    }
+}
+
+static COMPRESSED_HASHES: LazyLock<Mutex<HashMap<String, Arc<Mutex<Sha256>>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Arc<Mutex<Sha256>>,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.hasher.lock().unwrap().update(&buf[..count]);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct ZstdWriter<W: Write>(Option<zstd::stream::write::Decoder<'static, W>>);
+
+impl<W: Write> Write for ZstdWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.as_mut().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for ZstdWriter<W> {
+    fn drop(&mut self) {
+        if let Some(mut decoder) = self.0.take() {
+            let _ = decoder.flush();
+            let _ = decoder.into_inner().flush();
+        }
+    }
+}
+
+pub fn get_mode(metadata: &std::fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o7777
+    }
+    #[cfg(not(unix))]
+    match metadata.permissions().readonly() {
+        true => 0o444,
+        false => 0o644,
+    }
+}
+
+pub fn preserve(
+    path: &str,
+    modified: Option<SystemTime>,
+    mode: Option<u32>,
+) -> std::io::Result<()> {
+    if let Some(modified) = modified {
+        filetime::set_file_mtime(path, filetime::FileTime::from_system_time(modified))?;
+    }
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o7777))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(())
+}
+
 #[test]
 fn test_get_output_file_file_is_none_when_stdout() {
     let is_silet = true;
@@ -92,3 +528,113 @@ fn test_get_output_file_file_is_none_when_existingfile_and_not_silent() {
     assert_eq!(position, expected_position_byte);
     std::fs::remove_file(filename).unwrap();
 }
+
+#[test]
+fn test_preserve_sets_mtime_and_mode() {
+    let filename = "test_preserve_sets_mtime_and_mode";
+    File::create(filename).unwrap();
+    let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+
+    preserve(filename, Some(modified), Some(0o750)).unwrap();
+    let metadata = std::fs::metadata(filename).unwrap();
+    std::fs::remove_file(filename).unwrap();
+
+    assert_eq!(metadata.modified().unwrap(), modified);
+    #[cfg(unix)]
+    assert_eq!(get_mode(&metadata), 0o750);
+}
+
+#[cfg(test)]
+fn decompress_roundtrip(input: &str, compressed: Vec<u8>) {
+    let name = Slicer::filename_from_url(input).unwrap();
+    let output = "test_decompress_roundtrip_".to_string() + &name.replace('.', "_");
+    {
+        let mut out = get_decompressed_output(input, &output, true);
+        for chunk in compressed.chunks(7) {
+            out.write_all(chunk).unwrap();
+        }
+    }
+    let content = std::fs::read_to_string(&output).unwrap();
+    let compressed_hash = take_compressed_sha256(&output).unwrap();
+    std::fs::remove_file(&output).unwrap();
+
+    assert_eq!(content, "hello aim\n".repeat(100));
+    assert_eq!(
+        compressed_hash,
+        format!("{:x}", Sha256::digest(&compressed))
+    );
+}
+
+#[test]
+fn test_get_decompressed_output_works_when_gzip() {
+    let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    encoder
+        .write_all("hello aim\n".repeat(100).as_bytes())
+        .unwrap();
+    decompress_roundtrip(
+        "https://example.com/file.txt.gz?x=1",
+        encoder.finish().unwrap(),
+    );
+}
+
+#[test]
+fn test_get_decompressed_output_works_when_xz() {
+    let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+    encoder
+        .write_all("hello aim\n".repeat(100).as_bytes())
+        .unwrap();
+    decompress_roundtrip("https://example.com/file.txt.xz", encoder.finish().unwrap());
+}
+
+#[test]
+fn test_get_decompressed_output_works_when_zstd() {
+    let compressed = zstd::encode_all("hello aim\n".repeat(100).as_bytes(), 3).unwrap();
+    decompress_roundtrip("https://example.com/file.txt.zst", compressed);
+}
+
+#[test]
+fn test_compression_strip_extension_works_when_typical() {
+    assert_eq!(Compression::strip_extension("file.txt.gz"), "file.txt");
+    assert_eq!(Compression::strip_extension("file.txt"), "file.txt");
+    assert_eq!(Compression::strip_extension(".gz"), ".gz");
+}
+
+#[tokio::test]
+async fn test_channel_reader_withholds_data_when_writer_fails() {
+    use tokio::io::AsyncReadExt;
+    let (writer, mut reader) = channel(4);
+    writer.send(b"hello").await.unwrap();
+    writer
+        .fail(std::io::Error::other("checksum mismatch"))
+        .await;
+
+    let mut content = vec![];
+    let result = AsyncReadExt::read_to_end(&mut reader, &mut content).await;
+
+    assert!(result.is_err());
+    assert!(content.is_empty());
+}
+
+#[test]
+fn test_channel_reader_works_when_writer_closes() {
+    let (mut writer, mut reader) = channel(4);
+    writer.write_all(b"hello ").unwrap();
+    writer.write_all(b"aim").unwrap();
+    drop(writer);
+
+    let mut content = String::new();
+    reader.read_to_string(&mut content).unwrap();
+
+    assert_eq!(content, "hello aim");
+}
+
+#[test]
+fn test_channel_writer_hashes_sent_data() {
+    let (mut writer, _reader) = channel(4);
+    writer.write_all(b"hello aim").unwrap();
+
+    assert_eq!(
+        writer.sha256(),
+        format!("{:x}", Sha256::digest(b"hello aim"))
+    );
+}
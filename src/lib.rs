@@ -3,16 +3,26 @@ pub mod bar;
 pub mod consts;
 pub mod driver;
 pub mod error;
+pub mod extract;
 pub mod ftp;
 pub mod hash;
 pub mod http_serve_folder;
 pub mod https;
+pub mod install;
 pub mod io;
+#[cfg(test)]
+pub mod mock_http;
 pub mod netrc;
 pub mod question;
+pub mod release;
 pub mod s3;
 pub mod sftp;
 pub mod slicer;
 pub mod ssh;
 pub mod ssh_auth;
+pub mod ssh_config;
+pub mod ssh_proxy;
+pub mod state;
+pub mod timeout;
 pub mod tls;
+pub mod update;
@@ -1,20 +1,66 @@
 use autoclap::autoclap;
 use clap::Command;
 use clap::{Arg, ArgAction};
+use std::time::Duration;
 use std::{env, io};
 
 use aim::driver::Options;
+use aim::install::Installer;
+use aim::timeout::Timeouts;
+use aim::update::Updater;
 
 #[tokio::main]
 #[cfg(not(tarpaulin_include))]
 async fn main() {
     let (input, output, options) = parse_args().await.expect("Cannot parse args");
-    match aim::driver::Driver::dispatch(&input, &output, &options).await {
+    exit(aim::driver::Driver::dispatch(&input, &output, &options).await)
+}
+
+#[cfg(not(tarpaulin_include))]
+fn exit(result: io::Result<()>) -> ! {
+    match result {
         Ok(_) => std::process::exit(0),
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+            eprintln!("{e}");
+            std::process::exit(28)
+        }
         _ => std::process::exit(255),
     }
 }
 
+#[cfg(not(tarpaulin_include))]
+async fn run_subcommand(command: &str, args: &clap::ArgMatches, options: &Options) -> ! {
+    let value = |name: &str| {
+        args.try_get_one::<String>(name)
+            .ok()
+            .flatten()
+            .map(|s| s.as_str())
+    };
+    let installer = Installer::new(value("bin-dir").unwrap_or(""));
+    let result = match command {
+        "install" => installer
+            .install(value("SOURCE").unwrap(), value("name"), options)
+            .await
+            .map(|_| ()),
+        "list-installed" => {
+            for installed in installer.list() {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    installed.name, installed.version, installed.source, installed.path
+                );
+            }
+            Ok(())
+        }
+        "upgrade" => installer.upgrade(value("NAME"), options).await,
+        "uninstall" => installer.uninstall(value("NAME").unwrap()),
+        _ => unreachable!("Unknown subcommand {command}"),
+    };
+    if let Err(e) = &result {
+        eprintln!("{e}");
+    }
+    exit(result)
+}
+
 #[cfg(not(tarpaulin_include))]
 async fn parse_args() -> io::Result<(String, String, Options)> {
     let app: clap::Command = autoclap!()
@@ -22,7 +68,8 @@ async fn parse_args() -> io::Result<(String, String, Options)> {
             Arg::new("INPUT")
                 .help(
                     "Input to aim from.\n\
-                If no output provided and input is a folder, it will be served via http.",
+                If no output provided and input is a folder, it will be served via http.\n\
+                Use '-' or 'stdin' to upload from standard input.",
                 )
                 .required(false),
         )
@@ -33,8 +80,7 @@ async fn parse_args() -> io::Result<(String, String, Options)> {
             * If no output argument is present, writes to stdout.\n\
             * Downloading: if file supplied, writes to it.\n\
               \x20\x20* if output is '.': downloads to the same basename as the source.\n\
-              \x20\x20* if output is '+': downloads to the same basename as the source \n\
-              \x20\x20\x20\x20and attempts to decompress the archive based on the file's extension.\n\
+              \x20\x20* if output is '+': extracts the archive into the current folder.\n\
             * Uploading: directly uploads file to the URL.",
                 )
                 .required(false),
@@ -73,8 +119,42 @@ async fn parse_args() -> io::Result<(String, String, Options)> {
             Arg::new("update")
                 .long("update")
                 .short('u')
+                .value_name("VERSION")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("latest")
+                .help("Update the executable in-place, to the latest or the given version.\n\
+            The release asset is verified with its published sha256 and, if a public key is set, its signature.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("check-update")
+                .long("check-update")
                 .action(ArgAction::SetTrue)
-                .help("Update the executable in-place.")
+                .help("Report whether a newer version is available, without updating.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("rollback")
+                .long("rollback")
+                .action(ArgAction::SetTrue)
+                .help("Restore the executable replaced by the last update.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("update-source")
+                .long("update-source")
+                .value_name("SOURCE")
+                .help("Release to update from, as gh:owner/repo or gl:group/project.\n\
+            Defaults to $AIM_UPDATE_SOURCE or the aim GitHub releases.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("update-public-key")
+                .long("update-public-key")
+                .value_name("KEY")
+                .help("Base64 Ed25519 public key verifying the <asset>.sig of updates.\n\
+            Defaults to $AIM_UPDATE_PUBLIC_KEY.")
                 .required(false),
         )
         .arg(
@@ -83,28 +163,286 @@ async fn parse_args() -> io::Result<(String, String, Options)> {
                 .action(ArgAction::SetTrue)
                 .help("Disable automatic following of HTTP redirects.")
                 .required(false),
+        )
+        .arg(
+            Arg::new("method")
+                .long("method")
+                .short('X')
+                .help("HTTP method to upload with. Defaults to PUT, or POST when --form is used.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("form")
+                .long("form")
+                .short('F')
+                .action(ArgAction::Append)
+                .help("Upload as multipart/form-data. Repeatable.\n\
+            * name=value: adds a text field.\n\
+            * name=@path: adds a file field, streamed from path.\n\
+            If no file field is given, INPUT is sent as the 'file' field.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("upload-resume")
+                .long("upload-resume")
+                .value_parser(["content-range", "tus", "append", "none"])
+                .default_value("none")
+                .help("How interrupted uploads are resumed.\n\
+            * none: always uploads the whole file.\n\
+            * content-range: http(s), sends the missing bytes with a Content-Range header, for servers that accept it.\n\
+            * tus: http(s), uses the tus.io resumable upload protocol, OUTPUT is the creation URL.\n\
+            * append: ssh, appends the missing bytes once the remote file is checked to start with the local content.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("connect-timeout")
+                .long("connect-timeout")
+                .value_parser(clap::value_parser!(f64))
+                .help("Maximum time in seconds allowed for connecting to the remote.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("read-timeout")
+                .long("read-timeout")
+                .value_parser(clap::value_parser!(f64))
+                .help("Maximum time in seconds to wait for data before aborting.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("max-time")
+                .long("max-time")
+                .short('m')
+                .value_parser(clap::value_parser!(f64))
+                .help("Maximum time in seconds allowed for the whole transfer.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("speed-limit")
+                .long("speed-limit")
+                .short('Y')
+                .value_parser(clap::value_parser!(u64))
+                .help("Abort if slower than this many bytes/sec for --speed-time. Defaults to 1.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("speed-time")
+                .long("speed-time")
+                .short('y')
+                .value_parser(clap::value_parser!(f64))
+                .help("Seconds the transfer may stay below --speed-limit. Defaults to 30.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("output-template")
+                .long("output-template")
+                .short('o')
+                .help("Template for the downloaded file's path, i.e. {host}/{path}/{basename}.\n\
+            Available: {host}, {path}, {basename}, {date}.\n\
+            If OUTPUT is a directory, the path is relative to it.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("on-exists")
+                .long("on-exists")
+                .value_parser(["auto", "resume", "overwrite", "skip", "rename", "fail"])
+                .default_value("auto")
+                .help("What to do when the downloaded output file already exists.\n\
+            * auto: resume if it is a partial download of the same input, fail otherwise.\n\
+            * resume: append the missing bytes.\n\
+            * overwrite: download again from scratch.\n\
+            * skip: keep the file if its size (or sha256, if given) matches the remote.\n\
+            * rename: download to the first free name OUTPUT.1, OUTPUT.2, ...\n\
+            * fail: stop with an error.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("host-key-checking")
+                .long("host-key-checking")
+                .value_parser(["strict", "accept-new", "off"])
+                .default_value("accept-new")
+                .help("How to verify ssh/sftp host keys against ~/.ssh/known_hosts (or AIM_KNOWN_HOSTS).\n\
+            * strict: refuse hosts that are not known.\n\
+            * accept-new: ask before adding unknown hosts, refuse changed keys.\n\
+            * off: do not verify host keys.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("identity")
+                .long("identity")
+                .value_name("FILE")
+                .help("Private key to try for ssh/sftp, after ssh-agent and before the default ~/.ssh keys.\n\
+            Passphrase-protected keys read AIM_SSH_PASSPHRASE or ask for the passphrase.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("create-dirs")
+                .long("create-dirs")
+                .action(ArgAction::SetTrue)
+                .help("Create missing remote folders on ftp/sftp/ssh uploads, and the bucket on s3 uploads.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("cacert")
+                .long("cacert")
+                .value_name("FILE")
+                .help("PEM file with extra CA certificates to trust for https and ftps.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("insecure")
+                .long("insecure")
+                .short('k')
+                .action(ArgAction::SetTrue)
+                .help("Do not verify TLS certificates and host names for https and ftps.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("ftp-ssl")
+                .long("ftp-ssl")
+                .value_parser(["off", "try", "required"])
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_value("off")
+                .default_missing_value("try")
+                .help("Upgrade ftp:// connections to TLS with AUTH TLS, protecting the data channel too.\n\
+            * off: plain FTP.\n\
+            * try: use TLS if the server supports it, the same as a bare --ftp-ssl.\n\
+            * required: fail if the server does not support it (--ftp-ssl=required).\n\
+            Use ftps:// for implicit TLS, port 990 by default.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("ftp-mode")
+                .long("ftp-mode")
+                .value_parser(["passive", "epsv", "active"])
+                .default_value("passive")
+                .help("How ftp data connections are opened.\n\
+            * passive: PASV, the client connects to the server.\n\
+            * epsv: EPSV, passive for IPv6 and servers behind NAT.\n\
+            * active: PORT, the server connects back to the client.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("sftp-request-size")
+                .long("sftp-request-size")
+                .value_name("BYTES")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("262144")
+                .help("Bytes per sftp read/write request.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("sftp-requests")
+                .long("sftp-requests")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("16")
+                .help("Number of sftp requests kept in flight at once.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("no-clobber")
+                .long("no-clobber")
+                .action(ArgAction::SetTrue)
+                .help("Do not upload if the remote file already exists.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("only-if-newer")
+                .long("only-if-newer")
+                .action(ArgAction::SetTrue)
+                .help("Skip the download if the remote did not change since the last one.\n\
+            Uses ETag/Last-Modified on HTTP and size/modification time otherwise.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("preserve")
+                .long("preserve")
+                .short('p')
+                .action(ArgAction::SetTrue)
+                .help("Keep the modification time, and permissions for ssh/sftp, of the transferred file.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("compressed")
+                .long("compressed")
+                .action(ArgAction::SetTrue)
+                .help("Request a compressed HTTP response (gzip, br, zstd) and decode it transparently.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("decompress")
+                .long("decompress")
+                .action(ArgAction::SetTrue)
+                .help("Decompress .gz, .xz or .zst files while downloading them.\n\
+            The compression suffix is dropped from derived output names.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("extract-to")
+                .long("extract-to")
+                .value_name("DIR")
+                .help("Extract the downloaded archive (tar, tar.gz, tar.xz, tar.zst, zip) into DIR.\n\
+            The OUTPUT + is a shorthand for --extract-to .")
+                .required(false),
+        )
+        .arg(
+            Arg::new("strip-components")
+                .long("strip-components")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("0")
+                .help("Remove N leading path components of extracted entries.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .value_name("GLOB")
+                .action(ArgAction::Append)
+                .help("Only extract entries matching GLOB. Repeatable.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .action(ArgAction::Append)
+                .help("Do not extract entries matching GLOB. Repeatable.")
+                .required(false),
+        )
+        .subcommand(
+            Command::new("install")
+                .about("Download, verify and extract a tool, then place its executable on PATH.")
+                .arg(
+                    Arg::new("SOURCE")
+                        .help("URL of the release asset, gh:owner/repo[@tag] or gl:group/project[@tag].")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("name")
+                        .long("name")
+                        .help("Name of the executable. Derived from the asset name by default.")
+                        .required(false),
+                )
+                .arg(bin_dir_arg()),
+        )
+        .subcommand(Command::new("list-installed").about("List tools installed with aim install."))
+        .subcommand(
+            Command::new("upgrade")
+                .about("Upgrade all, or the named, tools installed with aim install.")
+                .arg(Arg::new("NAME").required(false)),
+        )
+        .subcommand(
+            Command::new("uninstall")
+                .about("Remove a tool installed with aim install.")
+                .arg(Arg::new("NAME").required(true)),
         );
     let args = app.clone().try_get_matches().unwrap_or_else(|e| e.exit());
 
-    if args.get_flag("update") {
-        tokio::task::spawn_blocking(move || match update() {
-            Err(e) => {
-                println!("ERROR: {e}");
-                ::std::process::exit(1);
-            }
-            Ok(()) => ::std::process::exit(0),
-        })
-        .await
-        .unwrap();
-    }
-
     if args.get_flag("version") {
         println!("{}", app.get_about().unwrap());
     }
-    let input = args
-        .get_one::<String>("INPUT")
-        .unwrap_or_else(|| ::std::process::exit(0));
-
     let output = args
         .get_one::<String>("OUTPUT")
         .map(|s| s.as_str())
@@ -113,34 +451,127 @@ async fn parse_args() -> io::Result<(String, String, Options)> {
     let silent = args.get_flag("silent");
     let interactive = args.get_flag("interactive");
     let no_follow_redirects = args.get_flag("no-follow-redirects");
+    let method = args
+        .get_one::<String>("method")
+        .map(|s| s.as_str())
+        .unwrap_or("");
+    let upload_resume = args.get_one::<String>("upload-resume").unwrap();
+    let form = args
+        .get_many::<String>("form")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default();
+    let seconds = |name: &str| {
+        args.get_one::<f64>(name)
+            .map(|s| Duration::from_secs_f64(*s))
+    };
+    let timeouts = Timeouts {
+        connect: seconds("connect-timeout"),
+        read: seconds("read-timeout"),
+        total: seconds("max-time"),
+        speed_limit: args.get_one::<u64>("speed-limit").copied(),
+        speed_time: seconds("speed-time"),
+    };
+    let on_exists = args.get_one::<String>("on-exists").unwrap();
+    let no_clobber = args.get_flag("no-clobber");
+    let only_if_newer = args.get_flag("only-if-newer");
+    let preserve = args.get_flag("preserve");
+    let compressed = args.get_flag("compressed");
+    let decompress = args.get_flag("decompress");
+    let extract_to = args
+        .get_one::<String>("extract-to")
+        .map(|s| s.as_str())
+        .unwrap_or("");
+    let strip_components = *args.get_one::<usize>("strip-components").unwrap();
+    let globs = |name: &str| {
+        args.get_many::<String>(name)
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default()
+    };
+    let output_template = args
+        .get_one::<String>("output-template")
+        .map(|s| s.as_str())
+        .unwrap_or("");
     let expected_sha256 = args
         .get_one::<String>("SHA256")
         .map(|s| s.as_str())
         .unwrap_or("");
 
-    Ok((
-        input.to_string(),
-        output.to_string(),
-        Options {
-            silent,
-            interactive,
-            expected_sha256: expected_sha256.to_string(),
-            no_follow_redirects,
-        },
-    ))
+    let options = Options {
+        silent,
+        interactive,
+        expected_sha256: expected_sha256.to_string(),
+        no_follow_redirects,
+        method: method.to_string(),
+        form,
+        upload_resume: upload_resume.to_string(),
+        timeouts,
+        output_template: output_template.to_string(),
+        on_exists: on_exists.to_string(),
+        no_clobber,
+        only_if_newer,
+        preserve,
+        compressed,
+        decompress,
+        extract_to: extract_to.to_string(),
+        strip_components,
+        include: globs("include"),
+        exclude: globs("exclude"),
+        host_key_checking: args
+            .get_one::<String>("host-key-checking")
+            .unwrap()
+            .to_string(),
+        identity: args
+            .get_one::<String>("identity")
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        sftp_request_size: *args.get_one::<usize>("sftp-request-size").unwrap(),
+        sftp_requests: *args.get_one::<usize>("sftp-requests").unwrap(),
+        create_dirs: args.get_flag("create-dirs"),
+        cacert: args
+            .get_one::<String>("cacert")
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        insecure: args.get_flag("insecure"),
+        ftp_ssl: args.get_one::<String>("ftp-ssl").unwrap().to_string(),
+        ftp_mode: args.get_one::<String>("ftp-mode").unwrap().to_string(),
+    };
+
+    run_update(&args, &options).await;
+    if let Some((command, subcommand_args)) = args.subcommand() {
+        run_subcommand(command, subcommand_args, &options).await;
+    }
+    let input = args
+        .get_one::<String>("INPUT")
+        .unwrap_or_else(|| ::std::process::exit(0));
+
+    Ok((input.to_string(), output.to_string(), options))
 }
 
-#[cfg(not(tarpaulin_include))]
-fn update() -> Result<(), Box<dyn ::std::error::Error>> {
-    let _status = self_update::backends::github::Update::configure()
-        .repo_owner("mihaigalos")
-        .repo_name(env!("CARGO_PKG_NAME"))
-        .bin_name(env!("CARGO_PKG_NAME"))
-        .show_download_progress(true)
-        .current_version(env!("CARGO_PKG_VERSION"))
-        .build()?
-        .update()?;
-    println!("✅ Done.");
-    Ok(())
+fn bin_dir_arg() -> Arg {
+    Arg::new("bin-dir")
+        .long("bin-dir")
+        .value_name("DIR")
+        .help("Folder to place executables in. Defaults to $AIM_BIN_DIR or ~/.local/bin.")
+        .required(false)
 }
 
+#[cfg(not(tarpaulin_include))]
+async fn run_update(args: &clap::ArgMatches, options: &Options) {
+    let value = |name: &str| args.get_one::<String>(name).map_or("", |s| s.as_str());
+    let updater = Updater::new(value("update-source"), value("update-public-key"));
+    let result = match (
+        args.get_one::<String>("update"),
+        args.get_flag("check-update"),
+        args.get_flag("rollback"),
+    ) {
+        (_, _, true) => Updater::rollback(),
+        (_, true, _) => updater.check(options).await.map(|_| ()),
+        (Some(version), _, _) => updater.update(version, options).await,
+        _ => return,
+    };
+    if let Err(e) = &result {
+        println!("ERROR: {e}");
+        ::std::process::exit(1);
+    }
+    ::std::process::exit(0);
+}
@@ -0,0 +1,113 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+pub struct MockRequest {
+    pub head: String,
+    pub body: Vec<u8>,
+}
+
+impl MockRequest {
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.head.lines().skip(1).find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            match key.trim().eq_ignore_ascii_case(name) {
+                true => Some(value.trim().to_string()),
+                false => None,
+            }
+        })
+    }
+}
+
+pub fn response(status: &str, headers: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+    let mut result = format!("HTTP/1.1 {status}\r\n");
+    for (name, value) in headers {
+        result += &format!("{name}: {value}\r\n");
+    }
+    if !headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+    {
+        result += &format!("Content-Length: {}\r\n", body.len());
+    }
+    result += "Connection: close\r\n\r\n";
+    let mut result = result.into_bytes();
+    result.extend_from_slice(body);
+    result
+}
+
+pub async fn serve(responses: Vec<Vec<u8>>) -> (String, JoinHandle<Vec<MockRequest>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        let mut requests = vec![];
+        for response in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            requests.push(read_request(&mut socket).await);
+            socket.write_all(&response).await.unwrap();
+            socket.shutdown().await.unwrap();
+        }
+        requests
+    });
+    (address, handle)
+}
+
+async fn read_request(socket: &mut TcpStream) -> MockRequest {
+    let mut data = vec![];
+    let mut buffer = [0; 8192];
+    let head_end = loop {
+        if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        let count = socket.read(&mut buffer).await.unwrap();
+        if count == 0 {
+            break data.len();
+        }
+        data.extend_from_slice(&buffer[..count]);
+    };
+    let head = String::from_utf8_lossy(&data[..head_end]).to_string();
+    let mut request = MockRequest {
+        head,
+        body: data[head_end..].to_vec(),
+    };
+
+    let is_chunked = request
+        .header("transfer-encoding")
+        .is_some_and(|v| v.eq_ignore_ascii_case("chunked"));
+    let content_length = request
+        .header("content-length")
+        .map(|v| v.parse::<usize>().unwrap());
+    loop {
+        let is_complete = match (is_chunked, content_length) {
+            (true, _) => request.body.ends_with(b"0\r\n\r\n"),
+            (false, Some(length)) => request.body.len() >= length,
+            (false, None) => true,
+        };
+        if is_complete {
+            break;
+        }
+        let count = socket.read(&mut buffer).await.unwrap();
+        if count == 0 {
+            break;
+        }
+        request.body.extend_from_slice(&buffer[..count]);
+    }
+    if is_chunked {
+        request.body = decode_chunked(&request.body);
+    }
+    request
+}
+
+fn decode_chunked(data: &[u8]) -> Vec<u8> {
+    let mut result = vec![];
+    let mut rest = data;
+    while let Some(pos) = rest.windows(2).position(|w| w == b"\r\n") {
+        let size = usize::from_str_radix(String::from_utf8_lossy(&rest[..pos]).trim(), 16).unwrap();
+        if size == 0 {
+            break;
+        }
+        result.extend_from_slice(&rest[pos + 2..pos + 2 + size]);
+        rest = &rest[pos + 2 + size + 2..];
+    }
+    result
+}
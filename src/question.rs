@@ -1,13 +1,25 @@
 #[cfg(not(debug_assertions))]
+use console::Term;
+#[cfg(not(debug_assertions))]
 use question::Answer;
 #[cfg(not(debug_assertions))]
 use question::Question;
+#[cfg(not(debug_assertions))]
+use std::io::IsTerminal;
 
 pub trait QuestionTrait {
     fn yes_no() -> bool;
+    fn confirm(question: &str) -> bool;
+    fn ask(prompt: &str) -> Option<String>;
+    fn secret(prompt: &str) -> Option<String>;
 }
 pub struct QuestionWrapped;
 
+pub fn ask_password<Q: QuestionTrait>(username: &str, server: &str) -> Option<String> {
+    let host = server.rsplit_once(':').map_or(server, |(host, _)| host);
+    Q::secret(&format!("{username}@{host}'s password: "))
+}
+
 #[cfg(not(debug_assertions))]
 impl QuestionTrait for QuestionWrapped {
     fn yes_no() -> bool {
@@ -16,6 +28,30 @@ impl QuestionTrait for QuestionWrapped {
             .confirm()
             == Answer::YES
     }
+
+    fn confirm(question: &str) -> bool {
+        QuestionWrapped::terminal().is_some()
+            && Question::new(question).default(Answer::NO).confirm() == Answer::YES
+    }
+
+    fn ask(prompt: &str) -> Option<String> {
+        let term = QuestionWrapped::terminal()?;
+        term.write_str(prompt).ok()?;
+        term.read_line().ok()
+    }
+
+    fn secret(prompt: &str) -> Option<String> {
+        let term = QuestionWrapped::terminal()?;
+        term.write_str(prompt).ok()?;
+        term.read_secure_line().ok()
+    }
+}
+
+#[cfg(not(debug_assertions))]
+impl QuestionWrapped {
+    fn terminal() -> Option<Term> {
+        std::io::stdin().is_terminal().then(Term::stderr)
+    }
 }
 
 #[cfg(debug_assertions)]
@@ -23,4 +59,16 @@ impl QuestionTrait for QuestionWrapped {
     fn yes_no() -> bool {
         true
     }
+
+    fn confirm(_question: &str) -> bool {
+        true
+    }
+
+    fn ask(_prompt: &str) -> Option<String> {
+        None
+    }
+
+    fn secret(_prompt: &str) -> Option<String> {
+        None
+    }
 }
@@ -0,0 +1,419 @@
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::Value;
+use std::env;
+use std::io;
+
+use crate::consts::CLIENT_ID;
+use crate::driver::Options;
+use crate::https::HTTPSHandler;
+
+const DEFAULT_GITHUB_API: &str = "https://api.github.com";
+const DEFAULT_GITLAB_API: &str = "https://gitlab.com";
+
+const IGNORED_EXTENSIONS: [&str; 13] = [
+    ".sha256",
+    ".sha256sum",
+    ".sha512",
+    ".sha512sum",
+    ".md5",
+    ".asc",
+    ".sig",
+    ".pem",
+    ".crt",
+    ".txt",
+    ".json",
+    ".sbom",
+    ".intoto.jsonl",
+];
+const PACKAGE_EXTENSIONS: [&str; 5] = [".deb", ".rpm", ".msi", ".dmg", ".pkg"];
+const OS_ALIASES: [(&str, &[&str]); 3] = [
+    ("linux", &["linux"]),
+    ("macos", &["darwin", "macos", "apple", "osx"]),
+    ("windows", &["windows", "win64", "win32", ".exe"]),
+];
+const ARCH_ALIASES: [(&str, &[&str]); 4] = [
+    ("x86_64", &["x86_64", "amd64", "x64"]),
+    ("aarch64", &["aarch64", "arm64"]),
+    ("arm", &["armv7", "armhf", "arm-"]),
+    ("x86", &["i686", "i386", "386"]),
+];
+
+#[derive(Debug, PartialEq)]
+pub enum Forge {
+    GitHub,
+    GitLab,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Shortcut {
+    pub forge: Forge,
+    pub project: String,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Asset {
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    pub sha256: Option<String>,
+    pub signature: Option<String>,
+}
+
+pub struct Platform {
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub env: &'static str,
+}
+
+impl Platform {
+    pub fn host() -> Platform {
+        Platform {
+            os: env::consts::OS,
+            arch: env::consts::ARCH,
+            env: match cfg!(target_env = "musl") {
+                true => "musl",
+                false => "gnu",
+            },
+        }
+    }
+}
+
+pub struct Release;
+impl Release {
+    pub fn is_shortcut(input: &str) -> bool {
+        input.starts_with("gh:") || input.starts_with("gl:")
+    }
+
+    pub fn parse(input: &str) -> Option<Shortcut> {
+        let (forge, rest) = match input.split_once(':')? {
+            ("gh", rest) => (Forge::GitHub, rest),
+            ("gl", rest) => (Forge::GitLab, rest),
+            _ => return None,
+        };
+        let (project, tag) = match rest.split_once('@') {
+            Some((project, "latest")) => (project, None),
+            Some((project, tag)) => (project, Some(tag.to_string())),
+            None => (rest, None),
+        };
+        if project.split('/').filter(|part| !part.is_empty()).count() < 2 {
+            return None;
+        }
+        Some(Shortcut {
+            forge,
+            project: project.trim_matches('/').to_string(),
+            tag,
+        })
+    }
+
+    pub async fn resolve(input: &str, options: &Options) -> io::Result<Asset> {
+        dotenvy::dotenv().ok();
+        let shortcut = Release::parse(input).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid release shortcut {input}, expected gh:owner/repo[@tag]"),
+            )
+        })?;
+        let api = match shortcut.forge {
+            Forge::GitHub => env::var("AIM_GITHUB_API").unwrap_or(DEFAULT_GITHUB_API.to_string()),
+            Forge::GitLab => env::var("AIM_GITLAB_API").unwrap_or(DEFAULT_GITLAB_API.to_string()),
+        };
+        let asset = Release::fetch(&shortcut, &api, &Platform::host(), options).await?;
+        if !options.silent {
+            println!("Resolved {input} to {}.", asset.url);
+        }
+        Ok(asset)
+    }
+
+    async fn fetch(
+        shortcut: &Shortcut,
+        api: &str,
+        platform: &Platform,
+        options: &Options,
+    ) -> io::Result<Asset> {
+        let api = api.trim_end_matches('/');
+        let url = match (&shortcut.forge, &shortcut.tag) {
+            (Forge::GitHub, None) => format!("{api}/repos/{}/releases/latest", shortcut.project),
+            (Forge::GitHub, Some(tag)) => {
+                format!("{api}/repos/{}/releases/tags/{tag}", shortcut.project)
+            }
+            (Forge::GitLab, tag) => format!(
+                "{api}/api/v4/projects/{}/releases/{}",
+                utf8_percent_encode(&shortcut.project, NON_ALPHANUMERIC),
+                tag.as_deref().unwrap_or("permalink/latest")
+            ),
+        };
+        let release: Value =
+            serde_json::from_str(&Release::get_text(&url, Some(&shortcut.forge), options).await?)?;
+        let assets = Release::assets(&shortcut.forge, &release);
+        let names: Vec<&str> = assets.iter().map(|asset| asset.name.as_str()).collect();
+        let index = Release::pick_asset(&names, platform).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "No asset of {} matches {}/{}/{}",
+                    shortcut.project, platform.os, platform.arch, platform.env
+                ),
+            )
+        })?;
+        let sha256 = match &assets[index].sha256 {
+            Some(sha256) => Some(sha256.clone()),
+            None => Release::published_sha256(&assets, &assets[index].name, options).await,
+        };
+        let asset = &assets[index];
+        let signature_name = asset.name.clone() + ".sig";
+        Ok(Asset {
+            name: asset.name.clone(),
+            version: release["tag_name"].as_str().unwrap_or_default().to_string(),
+            url: asset.url.clone(),
+            sha256,
+            signature: assets
+                .iter()
+                .find(|asset| asset.name == signature_name)
+                .map(|asset| asset.url.clone()),
+        })
+    }
+
+    fn assets(forge: &Forge, release: &Value) -> Vec<Asset> {
+        let (list, url_keys) = match forge {
+            Forge::GitHub => (&release["assets"], ["browser_download_url", "url"]),
+            Forge::GitLab => (&release["assets"]["links"], ["direct_asset_url", "url"]),
+        };
+        list.as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|asset| {
+                Some(Asset {
+                    name: asset["name"].as_str()?.to_string(),
+                    version: String::new(),
+                    url: url_keys
+                        .iter()
+                        .find_map(|key| asset[key].as_str())?
+                        .to_string(),
+                    sha256: asset["digest"]
+                        .as_str()
+                        .and_then(|digest| digest.strip_prefix("sha256:"))
+                        .map(|sha256| sha256.to_string()),
+                    signature: None,
+                })
+            })
+            .collect()
+    }
+
+    pub fn pick_asset(names: &[&str], platform: &Platform) -> Option<usize> {
+        let score = |name: &str| -> Option<i32> {
+            let name = name.to_lowercase();
+            if IGNORED_EXTENSIONS.iter().any(|e| name.ends_with(e)) {
+                return None;
+            }
+            let matches = |aliases: &[&str]| aliases.iter().any(|alias| name.contains(alias));
+            let mut score = 0;
+            for (os, aliases) in OS_ALIASES {
+                match (os == platform.os, matches(aliases)) {
+                    (true, true) => score += 8,
+                    (true, false) => return None,
+                    (false, true) => return None,
+                    (false, false) => {}
+                }
+            }
+            for (arch, aliases) in ARCH_ALIASES {
+                match (arch == platform.arch, matches(aliases)) {
+                    (true, true) => score += 4,
+                    (false, true) if !(arch == "x86" && name.contains("x86_64")) => return None,
+                    _ => {}
+                }
+            }
+            match (name.contains("musl"), name.contains("gnu")) {
+                (true, _) if platform.env == "musl" => score += 2,
+                (_, true) if platform.env == "gnu" => score += 2,
+                (true, _) => score += 1,
+                (_, true) => return None,
+                _ => {}
+            }
+            if PACKAGE_EXTENSIONS.iter().any(|e| name.ends_with(e)) {
+                score -= 1;
+            }
+            Some(score)
+        };
+        names
+            .iter()
+            .enumerate()
+            .filter_map(|(index, name)| Some((index, score(name)?)))
+            .fold(
+                None,
+                |best: Option<(usize, i32)>, (index, score)| match best {
+                    Some((_, best_score)) if best_score >= score => best,
+                    _ => Some((index, score)),
+                },
+            )
+            .map(|(index, _)| index)
+    }
+
+    async fn published_sha256(assets: &[Asset], name: &str, options: &Options) -> Option<String> {
+        let sidecars = [
+            name.to_string() + ".sha256",
+            name.to_string() + ".sha256sum",
+        ];
+        if let Some(sidecar) = assets.iter().find(|asset| sidecars.contains(&asset.name)) {
+            let text = Release::get_text(&sidecar.url, None, options).await.ok()?;
+            return text.split_whitespace().next().map(|s| s.to_lowercase());
+        }
+        let checksums = assets.iter().find(|asset| {
+            let lowercase = asset.name.to_lowercase();
+            (lowercase.contains("checksums") || lowercase.contains("sha256sums"))
+                && !lowercase.contains("sha512")
+        })?;
+        let text = Release::get_text(&checksums.url, None, options)
+            .await
+            .ok()?;
+        Release::find_checksum(&text, name)
+    }
+
+    fn find_checksum(text: &str, name: &str) -> Option<String> {
+        text.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            let (sha256, file) = (parts.next()?, parts.next()?);
+            match file.trim_start_matches('*') == name && sha256.len() == 64 {
+                true => Some(sha256.to_lowercase()),
+                false => None,
+            }
+        })
+    }
+
+    pub async fn get_text(url: &str, api: Option<&Forge>, options: &Options) -> io::Result<String> {
+        let mut request = HTTPSHandler::client(options)
+            .get(url)
+            .header(reqwest::header::USER_AGENT, CLIENT_ID);
+        match (api, env::var("GITHUB_TOKEN"), env::var("GITLAB_TOKEN")) {
+            (Some(Forge::GitHub), Ok(token), _) => request = request.bearer_auth(token),
+            (Some(Forge::GitLab), _, Ok(token)) => request = request.header("PRIVATE-TOKEN", token),
+            _ => {}
+        }
+        let response = request.send().await.map_err(io::Error::other)?;
+        if !response.status().is_success() {
+            return Err(io::Error::other(format!(
+                "GET {url} failed with {}",
+                response.status()
+            )));
+        }
+        response.text().await.map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+const LINUX: Platform = Platform {
+    os: "linux",
+    arch: "x86_64",
+    env: "gnu",
+};
+
+#[test]
+fn test_parse_works_when_typical() {
+    assert_eq!(
+        Release::parse("gh:Byron/dua-cli@latest"),
+        Some(Shortcut {
+            forge: Forge::GitHub,
+            project: "Byron/dua-cli".to_string(),
+            tag: None,
+        })
+    );
+    assert_eq!(
+        Release::parse("gl:group/sub/project@v1.0"),
+        Some(Shortcut {
+            forge: Forge::GitLab,
+            project: "group/sub/project".to_string(),
+            tag: Some("v1.0".to_string()),
+        })
+    );
+    assert_eq!(Release::parse("gh:dua-cli"), None);
+}
+
+#[test]
+fn test_pick_asset_works_when_typical() {
+    let names = [
+        "dua-v2.10.2-aarch64-unknown-linux-musl.tar.gz",
+        "dua-v2.10.2-x86_64-apple-darwin.tar.gz",
+        "dua-v2.10.2-x86_64-pc-windows-msvc.zip",
+        "dua-v2.10.2-x86_64-unknown-linux-musl.tar.gz",
+        "dua-v2.10.2-x86_64-unknown-linux-gnu.tar.gz",
+        "dua-v2.10.2-x86_64-unknown-linux-gnu.tar.gz.sha256",
+    ];
+    assert_eq!(Release::pick_asset(&names, &LINUX), Some(4));
+    let musl = Platform {
+        env: "musl",
+        ..LINUX
+    };
+    assert_eq!(Release::pick_asset(&names, &musl), Some(3));
+    let mac = Platform {
+        os: "macos",
+        arch: "aarch64",
+        env: "",
+    };
+    assert_eq!(Release::pick_asset(&names, &mac), None);
+}
+
+#[test]
+fn test_find_checksum_works_when_typical() {
+    let sha256 = "a".repeat(64);
+    let text = format!(
+        "{}  other.tar.gz\n{sha256} *tool-linux-amd64.tar.gz\n",
+        "b".repeat(64)
+    );
+    assert_eq!(
+        Release::find_checksum(&text, "tool-linux-amd64.tar.gz"),
+        Some(sha256)
+    );
+}
+
+#[tokio::test]
+async fn test_fetch_works_when_github_release_has_checksums() {
+    use crate::mock_http::{response, serve};
+    let sha256 = "c".repeat(64);
+    let checksums = format!("{sha256}  tool-v1-x86_64-unknown-linux-gnu.tar.gz\n");
+    let (downloads, _) = serve(vec![response("200 OK", &[], checksums.as_bytes())]).await;
+    let release = format!(
+        r#"{{"tag_name": "v1", "assets": [
+            {{"name": "tool-v1-x86_64-apple-darwin.tar.gz", "browser_download_url": "{downloads}/darwin"}},
+            {{"name": "tool-v1-x86_64-unknown-linux-gnu.tar.gz", "browser_download_url": "{downloads}/linux"}},
+            {{"name": "checksums.txt", "browser_download_url": "{downloads}/checksums.txt"}}
+        ]}}"#
+    );
+    let (api, server) = serve(vec![response("200 OK", &[], release.as_bytes())]).await;
+
+    let shortcut = Release::parse("gh:owner/tool").unwrap();
+    let asset = Release::fetch(&shortcut, &api, &LINUX, &Options::default()).await;
+    let requests = server.await.unwrap();
+
+    assert_eq!(
+        asset.unwrap(),
+        Asset {
+            name: "tool-v1-x86_64-unknown-linux-gnu.tar.gz".to_string(),
+            version: "v1".to_string(),
+            url: downloads + "/linux",
+            sha256: Some(sha256),
+            signature: None,
+        }
+    );
+    assert!(requests[0]
+        .head
+        .starts_with("GET /repos/owner/tool/releases/latest"));
+}
+
+#[tokio::test]
+async fn test_fetch_works_when_gitlab_release_has_digest() {
+    use crate::mock_http::{response, serve};
+    let release = r#"{"tag_name": "v2", "assets": {"links": [
+        {"name": "tool-linux-arm64", "direct_asset_url": "https://example.com/arm64"},
+        {"name": "tool-linux-amd64", "direct_asset_url": "https://example.com/amd64"}
+    ]}}"#;
+    let (api, server) = serve(vec![response("200 OK", &[], release.as_bytes())]).await;
+
+    let shortcut = Release::parse("gl:group/tool@v2").unwrap();
+    let asset = Release::fetch(&shortcut, &api, &LINUX, &Options::default()).await;
+    let requests = server.await.unwrap();
+
+    assert_eq!(asset.unwrap().url, "https://example.com/amd64");
+    assert!(requests[0]
+        .head
+        .starts_with("GET /api/v4/projects/group%2Ftool/releases/v2"));
+}
@@ -1,10 +1,12 @@
 extern crate http;
 extern crate s3;
 
+use futures_util::StreamExt;
 use std::io::Error;
 use std::str;
 
 use s3::bucket::Bucket;
+use s3::bucket_ops::BucketConfiguration;
 use s3::creds::Credentials;
 use s3::error::S3Error;
 use s3::region::Region;
@@ -12,10 +14,14 @@ use s3::region::Region;
 use crate::address::ParsedAddress;
 use crate::bar::WrappedBar;
 use crate::consts::*;
+use crate::driver::{Options, RemoteMetadata};
 use crate::error::HTTPHeaderError;
+use crate::error::TimeoutError;
 use crate::error::ValidateError;
 use crate::hash::HashChecker;
+use crate::io::{is_streamed, open_input, open_output, ChannelWriter, Input};
 use crate::question::*;
+use crate::timeout::Timeouts;
 use crate::tls::*;
 
 struct Storage {
@@ -33,51 +39,161 @@ impl S3 {
         output: &str,
         bar: &mut WrappedBar,
         expected_sha256: &str,
+        sink: Option<ChannelWriter>,
+        options: &Options,
     ) -> Result<(), ValidateError> {
-        S3::_get(input, output, bar).await.unwrap();
+        S3::_get(input, output, bar, sink, options).await?;
         HashChecker::check(output, expected_sha256)
     }
 
-    pub async fn put(input: &str, output: &str, bar: WrappedBar) -> Result<(), ValidateError> {
-        let (output, bucket) = S3::setup(output, bar.silent).await;
+    pub async fn put(
+        input: &str,
+        output: &str,
+        bar: WrappedBar,
+        source: Option<Input>,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        let (output, bucket) = S3::setup(output, bar.silent, &options.timeouts).await?;
+        if options.create_dirs {
+            S3::create_bucket(&bucket, bar.silent).await;
+        }
 
-        let mut async_input_file = tokio::fs::File::open(input) //TODO: when s3 provider crate has stream support implementing futures_core::stream::Stream used in resume, use io.rs::get_output() instead.
+        let mut input = open_input(input, source) //TODO: when s3 provider crate has stream support implementing futures_core::stream::Stream used in resume, use io.rs::get_output() instead.
             .await
-            .expect("Unable to open input file");
+            .expect("Unable to open input");
 
         let _ = bucket
-            .put_object_stream(&mut async_input_file, output)
+            .put_object_stream(&mut input.reader, output)
             .await
             .unwrap();
         Ok(())
     }
 
-    async fn _get(input: &str, output: &str, bar: &mut WrappedBar) -> Result<(), S3Error> {
-        let (path, bucket) = S3::setup(input, bar.silent).await;
+    async fn create_bucket(bucket: &Bucket, silent: bool) {
+        if bucket.exists().await.unwrap_or(true) {
+            return;
+        }
+        let credentials = bucket
+            .credentials()
+            .await
+            .expect("Cannot read S3 credentials");
+        let result = Bucket::create_with_path_style(
+            &bucket.name(),
+            bucket.region(),
+            credentials,
+            BucketConfiguration::default(),
+        )
+        .await;
+        if silent {
+            return;
+        }
+        match result {
+            Ok(response) if response.success() => {
+                println!("🪣 Created bucket {}.", bucket.name())
+            }
+            Ok(response) => eprintln!(
+                "⚠️ Cannot create bucket {}: {}",
+                bucket.name(),
+                response.response_text
+            ),
+            Err(e) => eprintln!("⚠️ Cannot create bucket {}: {e}", bucket.name()),
+        }
+    }
+
+    pub fn is_same_bucket(input: &str, output: &str) -> bool {
+        let input = ParsedAddress::parse_address(input, true);
+        let output = ParsedAddress::parse_address(output, true);
+        input.server == output.server
+            && input.username == output.username
+            && S3::get_bucket(&input) == S3::get_bucket(&output)
+    }
+
+    pub async fn copy(input: &str, output: &str, options: &Options) -> Result<(), ValidateError> {
+        let (source, bucket) = S3::setup(input, options.silent, &options.timeouts).await?;
+        let destination = S3::get_path_in_bucket(&ParsedAddress::parse_address(output, true));
+        bucket
+            .copy_object_internal(&source, &destination)
+            .await
+            .unwrap_or_else(|e| panic!("Cannot copy {input} to {output}: {e}"));
+        if !options.silent {
+            println!("Copied {input} to {output} on the server.");
+        }
+        Ok(())
+    }
+
+    pub async fn stat(
+        input: &str,
+        options: &Options,
+    ) -> Result<Option<RemoteMetadata>, ValidateError> {
+        let (path, bucket) = S3::setup(input, true, &options.timeouts).await?;
+        Ok(match bucket.head_object(&path).await {
+            Ok((head, 200)) => Some(RemoteMetadata {
+                size: head.content_length.map(|length| length as u64),
+                modified: head
+                    .last_modified
+                    .as_deref()
+                    .and_then(crate::https::parse_http_date),
+                etag: head.e_tag,
+                ..Default::default()
+            }),
+            _ => None,
+        })
+    }
+
+    async fn _get(
+        input: &str,
+        output: &str,
+        bar: &mut WrappedBar,
+        sink: Option<ChannelWriter>,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        let (path, bucket) = S3::setup(input, bar.silent, &options.timeouts).await?;
+        if sink.is_some() || is_streamed(output, options) {
+            let (mut out, _) = open_output(input, output, bar.silent, options, sink);
+            let mut stream = bucket.get_object_stream(&path).await.unwrap();
+            while let Some(chunk) = stream.bytes().next().await {
+                out.write_all(&chunk.unwrap())
+                    .await
+                    .expect("Error while writing to output.");
+            }
+            return Ok(());
+        }
         let mut async_output_file = tokio::fs::File::create(output) //TODO: when s3 provider crate has stream support implementing futures_core::stream::Stream used in resume, use io.rs::get_output() instead.
             .await
             .expect("Unable to open output file");
 
         bucket
             .get_object_to_writer(&path, &mut async_output_file)
-            .await?;
+            .await
+            .unwrap();
 
         Ok(())
     }
 
-    async fn setup(io: &str, silent: bool) -> (String, s3::bucket::Bucket) {
+    async fn setup(
+        io: &str,
+        silent: bool,
+        timeouts: &Timeouts,
+    ) -> Result<(String, s3::bucket::Bucket), TimeoutError> {
         let parsed_address = ParsedAddress::parse_address(io, silent);
         let io = S3::get_path_in_bucket(&parsed_address);
         let bucket = S3::get_bucket(&parsed_address);
-        let transport = S3::_get_transport::<TLS, QuestionWrapped>(&parsed_address.server);
+        let transport =
+            S3::_get_transport::<TLS, QuestionWrapped>(&parsed_address.server, timeouts)?;
         let fqdn = transport.to_string() + &parsed_address.server[..];
-        let bucket_kind = S3::_get_header(&fqdn, HTTP_HEADER_SERVER).await.unwrap();
+        let bucket_kind = timeouts
+            .connect(S3::_get_header(&fqdn, HTTP_HEADER_SERVER))
+            .await?
+            .unwrap();
         let (username, password) = S3::get_credentials(&parsed_address, silent);
         let backend = S3::new_storage(&bucket_kind, &username, &password, bucket, &fqdn);
-        let bucket = Bucket::new(bucket, backend.region, backend.credentials)
+        let mut bucket = Bucket::new(bucket, backend.region, backend.credentials)
             .unwrap()
             .with_path_style();
-        (io, *bucket)
+        if timeouts.read.is_some() {
+            bucket.set_request_timeout(timeouts.read);
+        }
+        Ok((io, *bucket))
     }
 
     fn get_credentials(parsed_address: &ParsedAddress, silent: bool) -> (String, String) {
@@ -170,18 +286,21 @@ impl S3 {
         Ok(result.to_str().unwrap().to_lowercase())
     }
 
-    fn _get_transport<T: TLSTrait, Q: QuestionTrait>(server: &str) -> &str {
+    fn _get_transport<T: TLSTrait, Q: QuestionTrait>(
+        server: &str,
+        timeouts: &Timeouts,
+    ) -> Result<&'static str, TimeoutError> {
         let parts: Vec<&str> = server.split(':').collect();
         assert_eq!(parts.len(), 2, "No port in URL. Stopping.");
         let host = parts[0];
         let port = parts[1];
-        if T::has_tls(host, port) {
+        Ok(if T::has_tls(host, port, timeouts)? {
             "https://"
         } else if Q::yes_no() {
             "http://"
         } else {
             ""
-        }
+        })
     }
 
     async fn _put_string(
@@ -312,7 +431,11 @@ mod tests {
         };
         let bucket = S3::get_bucket(&parsed_address);
 
-        let transport = S3::_get_transport::<TLS, QuestionWrapped>(&parsed_address.server);
+        let transport = S3::_get_transport::<TLS, QuestionWrapped>(
+            &parsed_address.server,
+            &Timeouts::default(),
+        )
+        .unwrap();
         let fqdn = transport.to_string() + &parsed_address.server[..];
         let bucket_kind = S3::_get_header(&fqdn, HTTP_HEADER_SERVER).await.unwrap();
         let backend = S3::new_storage(
@@ -346,7 +469,11 @@ mod tests {
         };
         let bucket = S3::get_bucket(&parsed_address);
 
-        let transport = S3::_get_transport::<TLS, QuestionWrapped>(&parsed_address.server);
+        let transport = S3::_get_transport::<TLS, QuestionWrapped>(
+            &parsed_address.server,
+            &Timeouts::default(),
+        )
+        .unwrap();
         let fqdn = transport.to_string() + &parsed_address.server[..];
         let bucket_kind = S3::_get_header(&fqdn, HTTP_HEADER_SERVER).await.unwrap();
         let backend = S3::new_storage(
@@ -386,7 +513,11 @@ mod tests {
         };
         let bucket = S3::get_bucket(&parsed_address);
 
-        let transport = S3::_get_transport::<TLS, QuestionWrapped>(&parsed_address.server);
+        let transport = S3::_get_transport::<TLS, QuestionWrapped>(
+            &parsed_address.server,
+            &Timeouts::default(),
+        )
+        .unwrap();
         let fqdn = transport.to_string() + &parsed_address.server[..];
         let bucket_kind = S3::_get_header(&fqdn, HTTP_HEADER_SERVER).await.unwrap();
         let backend = S3::new_storage(
@@ -449,12 +580,13 @@ fn test_get_transport_returns_http_transport_when_no_tls() {
     use crate::question::*;
     pub struct TlsMockNoTLS;
     impl TLSTrait for TlsMockNoTLS {
-        fn has_tls(_host: &str, _port: &str) -> bool {
-            false
+        fn has_tls(_host: &str, _port: &str, _timeouts: &Timeouts) -> Result<bool, TimeoutError> {
+            Ok(false)
         }
     }
     assert_eq!(
-        S3::_get_transport::<TlsMockNoTLS, QuestionWrapped>("dummyhost:9000"),
+        S3::_get_transport::<TlsMockNoTLS, QuestionWrapped>("dummyhost:9000", &Timeouts::default())
+            .unwrap(),
         "http://"
     );
 }
@@ -464,12 +596,16 @@ fn test_get_transport_returns_https_transport_when_has_tls() {
     use crate::question::*;
     pub struct TlsMockHasTLS;
     impl TLSTrait for TlsMockHasTLS {
-        fn has_tls(_host: &str, _port: &str) -> bool {
-            true
+        fn has_tls(_host: &str, _port: &str, _timeouts: &Timeouts) -> Result<bool, TimeoutError> {
+            Ok(true)
         }
     }
     assert_eq!(
-        S3::_get_transport::<TlsMockHasTLS, QuestionWrapped>("dummyhost:9000"),
+        S3::_get_transport::<TlsMockHasTLS, QuestionWrapped>(
+            "dummyhost:9000",
+            &Timeouts::default()
+        )
+        .unwrap(),
         "https://"
     );
 }
@@ -479,8 +615,8 @@ fn test_get_transport_returns_no_transport_when_no_tls() {
     use crate::question::*;
     pub struct TlsMockHasTLS;
     impl TLSTrait for TlsMockHasTLS {
-        fn has_tls(_host: &str, _port: &str) -> bool {
-            false
+        fn has_tls(_host: &str, _port: &str, _timeouts: &Timeouts) -> Result<bool, TimeoutError> {
+            Ok(false)
         }
     }
     struct QuestionWrappedMock;
@@ -488,9 +624,22 @@ fn test_get_transport_returns_no_transport_when_no_tls() {
         fn yes_no() -> bool {
             false
         }
+        fn confirm(_question: &str) -> bool {
+            false
+        }
+        fn ask(_prompt: &str) -> Option<String> {
+            None
+        }
+        fn secret(_prompt: &str) -> Option<String> {
+            None
+        }
     }
     assert_eq!(
-        S3::_get_transport::<TlsMockHasTLS, QuestionWrappedMock>("dummyhost:9000"),
+        S3::_get_transport::<TlsMockHasTLS, QuestionWrappedMock>(
+            "dummyhost:9000",
+            &Timeouts::default()
+        )
+        .unwrap(),
         ""
     );
 }
@@ -505,7 +654,9 @@ async fn test_get_transport_bucket_panics_when_no_port() {
         path_segments: vec!["test-bucket".to_string()],
         file: "".to_string(),
     };
-    let _ = S3::_get_transport::<TLS, QuestionWrapped>(&parsed_address.server);
+    let _ =
+        S3::_get_transport::<TLS, QuestionWrapped>(&parsed_address.server, &Timeouts::default())
+            .unwrap();
 }
 
 #[test]
@@ -575,7 +726,6 @@ mod test_mixins {
 
         let mut file = OpenOptions::new()
             .create(true)
-
             .append(true)
             .open(untildify("~/.aws/credentials"))
             .unwrap();
@@ -611,7 +761,6 @@ mod test_mixins {
 
         let mut file = OpenOptions::new()
             .create(true)
-
             .append(true)
             .open(untildify("~/.aws/credentials"))
             .unwrap();
@@ -1,25 +1,132 @@
 extern crate ssh2;
 
-use async_ssh2_lite::{AsyncSession, TokioTcpStream};
+use async_ssh2_lite::{AsyncFile, AsyncSession, AsyncSftp, TokioTcpStream};
+use futures::future::poll_fn;
 use futures::AsyncReadExt;
 use futures::AsyncSeekExt;
 use futures::AsyncWriteExt;
+use ssh2::{FileStat, OpenFlags, OpenType};
 
 use std::cmp::min;
+use std::collections::VecDeque;
+use std::future::Future;
 use std::io::Error;
 use std::io::SeekFrom;
 use std::net::ToSocketAddrs;
 use std::path::Path;
+use std::pin::Pin;
+use std::task::Poll;
 use tokio::io::AsyncReadExt as OtherAsyncReadExt;
-use tokio::io::AsyncSeekExt as OtherAsyncSeekExt;
 
 use crate::address::ParsedAddress;
 use crate::bar::WrappedBar;
-use crate::consts::*;
+use crate::driver::{Options, RemoteMetadata};
 use crate::error::ValidateError;
 use crate::hash::HashChecker;
-use crate::io::get_output;
-use crate::ssh_auth::get_possible_ssh_keys_path;
+use crate::io::{get_mode, open_input, open_output, ChannelWriter, Input, InputReader};
+use crate::question::{ask_password, QuestionWrapped};
+use crate::ssh_auth::{
+    has_agent, identity_files, known_hosts_path, passphrase, public_key_file, verify_host_key,
+    InteractivePrompt,
+};
+use crate::ssh_config::SshHost;
+use crate::ssh_proxy::dial_address;
+use crate::timeout::SpeedGuard;
+
+const DEFAULT_MODE: i32 = 0o644;
+const DEFAULT_DIR_MODE: i32 = 0o755;
+const DEFAULT_REQUEST_SIZE: usize = 256 * 1024;
+const DEFAULT_REQUESTS: usize = 16;
+
+type RemoteFile = AsyncFile<TokioTcpStream>;
+type Request<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Requests in flight on one session, yielded in the order they were pushed.
+/// Every wake-up polls all of them: the session socket only keeps the most
+/// recent waker, and a response read by one request may belong to another.
+struct Pipeline<'a, T> {
+    requests: VecDeque<(Request<'a, T>, Option<T>)>,
+}
+
+impl<T> Default for Pipeline<'_, T> {
+    fn default() -> Self {
+        Pipeline {
+            requests: VecDeque::new(),
+        }
+    }
+}
+
+impl<'a, T> Pipeline<'a, T> {
+    fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    fn push(&mut self, request: Request<'a, T>) {
+        self.requests.push_back((request, None));
+    }
+
+    async fn next(&mut self) -> Option<T> {
+        poll_fn(|cx| loop {
+            let mut is_progress = false;
+            for (request, output) in self.requests.iter_mut() {
+                if output.is_none() {
+                    if let Poll::Ready(value) = request.as_mut().poll(cx) {
+                        *output = Some(value);
+                        is_progress = true;
+                    }
+                }
+            }
+            match self.requests.front() {
+                None => return Poll::Ready(None),
+                Some((_, Some(_))) => return Poll::Ready(self.requests.pop_front().unwrap().1),
+                Some(_) if !is_progress => return Poll::Pending,
+                Some(_) => {}
+            }
+        })
+        .await
+    }
+}
+
+async fn read_at(
+    file: &mut RemoteFile,
+    offset: u64,
+    buffer: &mut Vec<u8>,
+    length: usize,
+) -> std::io::Result<()> {
+    file.seek(SeekFrom::Start(offset)).await?;
+    buffer.resize(length, 0);
+    let mut filled = 0;
+    while filled < length {
+        match file.read(&mut buffer[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    buffer.truncate(filled);
+    Ok(())
+}
+
+async fn write_at(file: &mut RemoteFile, offset: u64, buffer: &[u8]) -> std::io::Result<()> {
+    file.seek(SeekFrom::Start(offset)).await?;
+    file.write_all(buffer).await
+}
+
+async fn fill(
+    reader: &mut InputReader,
+    buffer: &mut Vec<u8>,
+    length: usize,
+) -> std::io::Result<()> {
+    buffer.resize(length, 0);
+    let mut filled = 0;
+    while filled < length {
+        match reader.read(&mut buffer[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    buffer.truncate(filled);
+    Ok(())
+}
 
 pub struct SFTPHandler;
 impl SFTPHandler {
@@ -28,150 +135,336 @@ impl SFTPHandler {
         output: &str,
         bar: &mut WrappedBar,
         expected_sha256: &str,
+        sink: Option<ChannelWriter>,
+        options: &Options,
     ) -> Result<(), ValidateError> {
-        SFTPHandler::_get(input, output, bar).await?;
+        SFTPHandler::_get(input, output, bar, sink, options).await?;
         HashChecker::check(output, expected_sha256)
     }
-    async fn _get(input: &str, output: &str, bar: &mut WrappedBar) -> Result<(), ValidateError> {
-        let (session, remote_file) = SFTPHandler::setup_session(input, bar.silent).await;
-        let (mut out, mut transferred) = get_output(output, bar.silent);
+    async fn _get(
+        input: &str,
+        output: &str,
+        bar: &mut WrappedBar,
+        sink: Option<ChannelWriter>,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        let timeouts = &options.timeouts;
+        let (request_size, requests) = SFTPHandler::pipelining(options);
+        let (session, remote_file) = SFTPHandler::setup_session(input, bar.silent, options).await?;
+        let (mut out, mut transferred) = open_output(input, output, bar.silent, options, sink);
         let sftp = session.sftp().await.unwrap();
+        let remote_path = Path::new(&remote_file);
         let stat = sftp
-            .stat(Path::new(&remote_file))
+            .stat(remote_path)
             .await
             .expect("Cannot stat remote SFTP file");
-        let mut remote_file = sftp
-            .open(Path::new(&remote_file))
-            .await
-            .expect("Cannot open remote SFTP file");
         let total_size = stat.size.expect("Cannot get remote SFTP file size");
         bar.set_length(total_size);
 
-        remote_file
-            .seek(SeekFrom::Current(transferred as i64))
-            .await
-            .expect("Cannot seek in SFTP file");
+        let mut offsets = (transferred..total_size).step_by(request_size);
+        let mut files = vec![];
+        let mut buffers = vec![];
+        let mut pipeline = Pipeline::default();
+        let mut guard = SpeedGuard::new(timeouts);
         loop {
-            let mut buffer = vec![0; BUFFER_SIZE];
-            let byte_count = remote_file
-                .read(&mut buffer)
-                .await
-                .expect("Cannot read SFTP stream");
-            buffer.truncate(byte_count);
-            if !buffer.is_empty() {
-                out.write_all(&buffer)
-                    .map_err(|_| "Error while writing to output")
-                    .unwrap();
-                let new = min(transferred + (byte_count as u64), total_size);
-                transferred = new;
-                bar.set_position(new);
-            } else {
-                break;
+            while pipeline.len() < requests {
+                let Some(offset) = offsets.next() else {
+                    break;
+                };
+                let mut file = match files.pop() {
+                    Some(file) => file,
+                    None => sftp
+                        .open(remote_path)
+                        .await
+                        .expect("Cannot open remote SFTP file"),
+                };
+                let mut buffer: Vec<u8> = buffers.pop().unwrap_or_default();
+                pipeline.push(Box::pin(async move {
+                    let result = timeouts
+                        .read(read_at(&mut file, offset, &mut buffer, request_size))
+                        .await;
+                    (file, buffer, result)
+                }));
             }
+            let Some((file, buffer, result)) = pipeline.next().await else {
+                break;
+            };
+            result?.expect("Cannot read SFTP stream");
+            guard.update(buffer.len() as u64)?;
+            out.write_all(&buffer)
+                .await
+                .map_err(|_| "Error while writing to output")
+                .unwrap();
+            transferred = min(transferred + buffer.len() as u64, total_size);
+            bar.set_position(transferred);
+            files.push(file);
+            buffers.push(buffer);
         }
         bar.finish_download(input, output);
         Ok(())
     }
 
-    pub async fn put(input: &str, output: &str, mut bar: WrappedBar) -> Result<(), ValidateError> {
-        let mut file = tokio::fs::File::open(&input)
+    pub async fn put(
+        input: &str,
+        output: &str,
+        mut bar: WrappedBar,
+        source: Option<Input>,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        let timeouts = &options.timeouts;
+        let (request_size, requests) = SFTPHandler::pipelining(options);
+        let source = open_input(input, source)
             .await
             .expect("Cannot read input file");
-        let total_size = file
-            .metadata()
-            .await
-            .expect("Cannot determine input file length")
-            .len();
-        let (session, remote_file) = SFTPHandler::setup_session(output, bar.silent).await;
+        let is_resumable = source.is_resumable();
+        let Input {
+            reader: mut file,
+            size: total_size,
+            metadata,
+        } = source;
+        let (session, remote_file) =
+            SFTPHandler::setup_session(output, bar.silent, options).await?;
+        let remote_path = Path::new(&remote_file).to_path_buf();
         let sftp = session.sftp().await.unwrap();
-        let stat = sftp.stat(Path::new(&remote_file)).await;
-        let (mut remote_file, mut transferred) = match stat {
-            Ok(v) => (
-                sftp.open(Path::new(&remote_file))
-                    .await
-                    .expect("Cannot open remote SFTP file"),
-                v.size.expect("Cannot determine remote SFTP file size"),
+        if options.create_dirs {
+            SFTPHandler::create_dirs(&sftp, &remote_path).await;
+        }
+        let remote_size = match is_resumable {
+            true => sftp
+                .stat(&remote_path)
+                .await
+                .ok()
+                .and_then(|stat| stat.size),
+            false => None,
+        };
+        let (flags, mut transferred) = match (remote_size, total_size) {
+            (Some(size), Some(total)) if size <= total => (OpenFlags::WRITE, size),
+            _ => (
+                OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+                0,
             ),
-            Err(_) => (sftp.create(Path::new(&remote_file)).await.unwrap(), 0),
         };
-        bar.set_length(transferred);
-
-        remote_file
-            .seek(SeekFrom::Current(transferred as i64))
+        let mut files = vec![sftp
+            .open_mode(&remote_path, flags, DEFAULT_MODE, OpenType::File)
             .await
-            .expect("Cannot seek in remote SFTP file");
-        file.seek(SeekFrom::Current(transferred as i64))
+            .expect("Cannot open remote SFTP file")];
+        bar.set_size(total_size);
+
+        file.skip(transferred)
             .await
             .expect("Cannot seek in local file");
+        let mut offset = transferred;
+        let mut buffers = vec![];
+        let mut is_eof = false;
+        let mut pipeline = Pipeline::default();
+        let mut guard = SpeedGuard::new(timeouts);
         loop {
-            let mut buffer = vec![0; BUFFER_SIZE];
-            let byte_count = file
-                .read(&mut buffer)
-                .await
-                .expect("Cannot read local file stream");
-            buffer.truncate(byte_count);
-            if !buffer.is_empty() {
-                remote_file
-                    .write_all(&buffer)
+            while !is_eof && pipeline.len() < requests {
+                let mut buffer: Vec<u8> = buffers.pop().unwrap_or_default();
+                fill(&mut file, &mut buffer, request_size)
                     .await
-                    .expect("Cannot write local file stream");
-                let new = min(transferred + (byte_count as u64), total_size);
-                transferred = new;
-                bar.set_position(new);
-            } else {
+                    .expect("Cannot read local file stream");
+                if buffer.is_empty() {
+                    is_eof = true;
+                    break;
+                }
+                let mut remote = match files.pop() {
+                    Some(remote) => remote,
+                    None => sftp
+                        .open_mode(&remote_path, OpenFlags::WRITE, DEFAULT_MODE, OpenType::File)
+                        .await
+                        .expect("Cannot open remote SFTP file"),
+                };
+                let chunk_offset = offset;
+                offset += buffer.len() as u64;
+                pipeline.push(Box::pin(async move {
+                    let result = timeouts
+                        .read(write_at(&mut remote, chunk_offset, &buffer))
+                        .await;
+                    (remote, buffer, result)
+                }));
+            }
+            let Some((remote, buffer, result)) = pipeline.next().await else {
                 break;
+            };
+            let result = match result {
+                Ok(Ok(())) => guard.update(buffer.len() as u64),
+                Ok(Err(e)) => {
+                    SFTPHandler::truncate(&sftp, &remote_path, transferred).await;
+                    panic!("Cannot write local file stream: {e}");
+                }
+                Err(e) => Err(e),
+            };
+            let new = transferred + buffer.len() as u64;
+            transferred = total_size.map_or(new, |total_size| min(new, total_size));
+            if let Err(e) = result {
+                SFTPHandler::truncate(&sftp, &remote_path, transferred).await;
+                return Err(e.into());
             }
+            bar.set_position(transferred);
+            files.push(remote);
+            buffers.push(buffer);
         }
-        bar.finish_download(input, output);
+        bar.finish_upload(input, output);
+        drop(files);
 
+        if let Some(metadata) = metadata.filter(|_| options.preserve) {
+            let seconds = |time: std::io::Result<std::time::SystemTime>| {
+                time.ok()?
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .ok()
+                    .map(|d| d.as_secs())
+            };
+            let stat = FileStat {
+                size: None,
+                uid: None,
+                gid: None,
+                perm: Some(get_mode(&metadata)),
+                atime: seconds(metadata.accessed()),
+                mtime: seconds(metadata.modified()),
+            };
+            sftp.setstat(&remote_path, stat)
+                .await
+                .expect("Cannot set remote SFTP file attributes");
+        }
         Ok(())
     }
-    async fn setup_session(address: &str, silent: bool) -> (AsyncSession<TokioTcpStream>, String) {
-        let parsed_address = ParsedAddress::parse_address(address, silent);
+    fn pipelining(options: &Options) -> (usize, usize) {
+        let or_default = |value: usize, default: usize| match value {
+            0 => default,
+            value => value,
+        };
+        (
+            or_default(options.sftp_request_size, DEFAULT_REQUEST_SIZE),
+            or_default(options.sftp_requests, DEFAULT_REQUESTS),
+        )
+    }
+
+    async fn create_dirs(sftp: &AsyncSftp<TokioTcpStream>, path: &Path) {
+        let mut missing = vec![];
+        for dir in path.ancestors().skip(1) {
+            if dir.as_os_str().is_empty() || dir == Path::new("/") {
+                break;
+            }
+            if sftp.stat(dir).await.is_ok() {
+                break;
+            }
+            missing.push(dir);
+        }
+        for dir in missing.into_iter().rev() {
+            sftp.mkdir(dir, DEFAULT_DIR_MODE)
+                .await
+                .unwrap_or_else(|e| panic!("Cannot create remote SFTP folder {dir:?}: {e}"));
+        }
+    }
+
+    async fn truncate(sftp: &AsyncSftp<TokioTcpStream>, path: &Path, size: u64) {
+        let stat = FileStat {
+            size: Some(size),
+            uid: None,
+            gid: None,
+            perm: None,
+            atime: None,
+            mtime: None,
+        };
+        let _ = sftp.setstat(path, stat).await;
+    }
+
+    pub async fn stat(
+        input: &str,
+        options: &Options,
+    ) -> Result<Option<RemoteMetadata>, ValidateError> {
+        let (session, remote_file) = SFTPHandler::setup_session(input, true, options).await?;
+        let sftp = session.sftp().await.unwrap();
+        Ok(sftp
+            .stat(Path::new(&remote_file))
+            .await
+            .ok()
+            .map(|stat| RemoteMetadata {
+                size: stat.size,
+                permissions: stat.perm.map(|perm| perm & 0o7777),
+                modified: stat
+                    .mtime
+                    .map(|mtime| std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime)),
+                ..Default::default()
+            }))
+    }
+
+    async fn setup_session(
+        address: &str,
+        silent: bool,
+        options: &Options,
+    ) -> Result<(AsyncSession<TokioTcpStream>, String), ValidateError> {
+        let timeouts = &options.timeouts;
+        let mut parsed_address = ParsedAddress::parse_address(address, silent);
+        let host = SshHost::resolve(&mut parsed_address, address);
+        let dial = dial_address(&host, &parsed_address, options, silent)?;
 
-        let addr = parsed_address
-            .server
-            .to_socket_addrs()
-            .unwrap()
-            .next()
+        let addr = dial.to_socket_addrs().unwrap().next().unwrap();
+        let stream = timeouts
+            .connect(TokioTcpStream::connect(addr))
+            .await?
             .unwrap();
-        let stream = TokioTcpStream::connect(addr).await.unwrap();
         let mut session = AsyncSession::new(stream, None).unwrap();
-        session.handshake().await.expect("SFTP handshake failed");
+        timeouts
+            .connect(session.handshake())
+            .await?
+            .expect("SFTP handshake failed");
+        verify_host_key::<QuestionWrapped>(
+            session.known_hosts().expect("Cannot read SFTP known hosts"),
+            &known_hosts_path(),
+            session.host_key(),
+            &parsed_address.server,
+            &options.host_key_checking,
+            silent,
+        )?;
+        let username = &parsed_address.username;
         if parsed_address.password != "anonymous" {
-            session
-                .userauth_password(&parsed_address.username, &parsed_address.password)
-                .await
-                .expect("SFTP Authentication failed");
+            let _ = session
+                .userauth_password(username, &parsed_address.password)
+                .await;
         } else {
-            let ssh_keys = get_possible_ssh_keys_path(silent);
-            let mut is_ok = false;
-            for ssh_key in ssh_keys.iter() {
-                if session
+            if has_agent() {
+                let _ = session.userauth_agent_with_try_next(username).await;
+            }
+            for identity in identity_files(&options.identity, &host.identity_files, silent) {
+                if session.authenticated() {
+                    break;
+                }
+                let _ = session
                     .userauth_pubkey_file(
-                        &parsed_address.username,
-                        Some(Path::new(&(ssh_key.to_owned() + ".pub"))),
-                        Path::new(ssh_key),
-                        None,
+                        username,
+                        public_key_file(&identity).as_deref(),
+                        &identity,
+                        passphrase::<QuestionWrapped>(&identity).as_deref(),
                     )
-                    .await
-                    .is_ok()
+                    .await;
+            }
+            if !session.authenticated() {
+                let mut prompt = InteractivePrompt::<QuestionWrapped>::default();
+                let _ = session
+                    .userauth_keyboard_interactive(username, &mut prompt)
+                    .await;
+            }
+            if !session.authenticated() {
+                if let Some(password) =
+                    ask_password::<QuestionWrapped>(username, &parsed_address.server)
                 {
-                    is_ok = true;
-                    break;
+                    let _ = session.userauth_password(username, &password).await;
                 }
             }
-
-            if !is_ok {
-                println!("SFTP Authentication failed. Please specify a user: sftp://user@address");
-            }
+        }
+        if !session.authenticated() {
+            return Err(ValidateError::Unauthorized {
+                host: format!("{username}@{}", parsed_address.server),
+            });
         }
 
         let remote_file = String::from("/")
             + &parsed_address.path_segments.join("/")[..]
             + "/"
             + &parsed_address.file[..];
-        (session, remote_file)
+        Ok((session, remote_file))
     }
 
     pub async fn get_links(_input: String) -> Result<Vec<String>, Error> {
@@ -184,3 +477,34 @@ impl SFTPHandler {
 async fn test_should_panic_when_not_implemented() {
     let _ = SFTPHandler::get_links("dummy".to_string()).await;
 }
+
+#[tokio::test]
+async fn test_pipeline_yields_in_order_when_completed_out_of_order() {
+    let mut pipeline = Pipeline::default();
+    for (id, millis) in [(0, 30), (1, 10), (2, 20)] {
+        pipeline.push(Box::pin(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(millis)).await;
+            id
+        }));
+    }
+    let started = std::time::Instant::now();
+
+    let mut actual = vec![];
+    while let Some(id) = pipeline.next().await {
+        actual.push(id);
+    }
+
+    assert_eq!(actual, vec![0, 1, 2]);
+    assert!(started.elapsed() < std::time::Duration::from_millis(60));
+    assert_eq!(pipeline.len(), 0);
+}
+
+#[test]
+fn test_pipelining_uses_defaults_when_unset() {
+    let options = Options {
+        sftp_requests: 4,
+        ..Default::default()
+    };
+
+    assert_eq!(SFTPHandler::pipelining(&options), (DEFAULT_REQUEST_SIZE, 4));
+}
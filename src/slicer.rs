@@ -1,8 +1,12 @@
+use percent_encoding::percent_decode_str;
+
 pub struct Slicer;
 impl Slicer {
     pub fn target_with_extension(s: &str) -> &str {
-        let pos_of_last_slash = s.rfind('/').unwrap();
-        &s[pos_of_last_slash + 1..]
+        match s.rfind('/') {
+            Some(pos_of_last_slash) => &s[pos_of_last_slash + 1..],
+            None => s,
+        }
     }
 
     pub fn target_without_extension(s: &str) -> &str {
@@ -21,6 +25,55 @@ impl Slicer {
         let pos_of_first_dash = target_with_extension.find('-').unwrap();
         &target_with_extension[..pos_of_first_dash]
     }
+
+    pub fn filename_from_url(url: &str) -> Option<String> {
+        let end = url.find(['?', '#']).unwrap_or(url.len());
+        let name = Slicer::target_with_extension(&url[..end]);
+        Slicer::sanitize(&percent_decode_str(name).decode_utf8_lossy())
+    }
+
+    pub fn filename_from_content_disposition(header: &str) -> Option<String> {
+        let mut filename = None;
+        let mut filename_extended = None;
+        for parameter in header.split(';').skip(1) {
+            let Some((key, value)) = parameter.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "filename*" => {
+                    let mut parts = value.splitn(3, '\'');
+                    let (Some(charset), Some(_language), Some(encoded)) =
+                        (parts.next(), parts.next(), parts.next())
+                    else {
+                        continue;
+                    };
+                    let decoded = percent_decode_str(encoded);
+                    filename_extended = match charset.to_lowercase().as_str() {
+                        "utf-8" => decoded.decode_utf8().ok().map(|s| s.to_string()),
+                        _ => Some(decoded.map(|b| b as char).collect()),
+                    };
+                }
+                "filename" => {
+                    filename = Some(value.trim_matches('"').replace("\\\"", "\""));
+                }
+                _ => {}
+            }
+        }
+        filename_extended
+            .or(filename)
+            .and_then(|name| Slicer::sanitize(&name))
+    }
+
+    pub fn sanitize(name: &str) -> Option<String> {
+        let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+        let name: String = name.chars().filter(|c| !c.is_control()).collect();
+        let name = name.trim();
+        match name {
+            "" | "." | ".." => None,
+            _ => Some(name.to_string()),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -53,4 +106,52 @@ mod tests {
         let expected = "dua";
         assert_eq!(is, expected);
     }
+    #[test]
+    fn filename_from_url_strips_query() {
+        let is = Slicer::filename_from_url(
+            "https://bucket.s3.amazonaws.com/asset.tar.gz?X-Amz-Signature=abc/def#top",
+        );
+        assert_eq!(is, Some("asset.tar.gz".to_string()));
+    }
+    #[test]
+    fn filename_from_url_percent_decodes() {
+        let is = Slicer::filename_from_url("https://example.com/files/my%20file%2Etxt");
+        assert_eq!(is, Some("my file.txt".to_string()));
+    }
+    #[test]
+    fn filename_from_url_is_none_when_no_name() {
+        assert_eq!(
+            Slicer::filename_from_url("https://example.com/?id=42/"),
+            None
+        );
+        assert_eq!(
+            Slicer::filename_from_url("https://example.com/%2E%2E"),
+            None
+        );
+    }
+    #[test]
+    fn filename_from_content_disposition_works_when_quoted() {
+        let is =
+            Slicer::filename_from_content_disposition("attachment; filename=\"report 2024.pdf\"");
+        assert_eq!(is, Some("report 2024.pdf".to_string()));
+    }
+    #[test]
+    fn filename_from_content_disposition_prefers_extended() {
+        let is = Slicer::filename_from_content_disposition(
+            "attachment; filename*=UTF-8''%E2%82%AC%20rates.txt; filename=\"rates.txt\"",
+        );
+        assert_eq!(is, Some("€ rates.txt".to_string()));
+    }
+    #[test]
+    fn filename_from_content_disposition_rejects_traversal() {
+        let is =
+            Slicer::filename_from_content_disposition("attachment; filename=\"../../etc/passwd\"");
+        assert_eq!(is, Some("passwd".to_string()));
+        let is = Slicer::filename_from_content_disposition("attachment; filename=\"..\"");
+        assert_eq!(is, None);
+    }
+    #[test]
+    fn filename_from_content_disposition_is_none_when_inline() {
+        assert_eq!(Slicer::filename_from_content_disposition("inline"), None);
+    }
 }
@@ -1,16 +1,45 @@
 extern crate ssh2;
 
-use ssh2::Session;
-use std::fs::File;
-use std::io::Error;
-use std::net::TcpStream;
+use sha2::{Digest, Sha256};
+use ssh2::{Channel, Session};
+use std::io::{Error, Read, Write};
 use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
+use tokio::runtime::Handle;
 
 use crate::address::ParsedAddress;
 use crate::bar::WrappedBar;
-use crate::error::ValidateError;
+use crate::driver::{Options, RemoteMetadata};
+use crate::error::{TimeoutError, ValidateError};
 use crate::hash::HashChecker;
-use crate::ssh_auth::get_possible_ssh_keys_path;
+use crate::io::{get_mode, open_input, open_output, ChannelWriter, Input, InputReader};
+use crate::question::{ask_password, QuestionWrapped};
+use crate::ssh_auth::{
+    has_agent, identity_files, known_hosts_path, passphrase, public_key_file, verify_host_key,
+    InteractivePrompt,
+};
+use crate::ssh_config::SshHost;
+use crate::ssh_proxy::dial_address;
+use crate::timeout::{timeout_from_io, GuardedReader};
+
+const DEFAULT_MODE: i32 = 0o644;
+
+async fn blocking<T, F>(function: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match tokio::task::spawn_blocking(function).await {
+        Ok(value) => value,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+fn quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', "'\\''"))
+}
+const LIBSSH2_ERROR_TIMEOUT: i32 = -9;
 
 pub struct SSHHandler;
 impl SSHHandler {
@@ -19,90 +48,370 @@ impl SSHHandler {
         output: &str,
         bar: &mut WrappedBar,
         expected_sha256: &str,
+        sink: Option<ChannelWriter>,
+        options: &Options,
     ) -> Result<(), ValidateError> {
-        SSHHandler::_get(input, output, bar).await?;
+        SSHHandler::_get(input, output, bar, sink, options).await?;
         HashChecker::check(output, expected_sha256)
     }
-    async fn _get(input: &str, output: &str, bar: &mut WrappedBar) -> Result<(), ValidateError> {
-        let (session, remote_file) = SSHHandler::setup_session(input, bar.silent);
+    async fn _get(
+        input: &str,
+        output: &str,
+        bar: &mut WrappedBar,
+        sink: Option<ChannelWriter>,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        let (input, output, options) = (input.to_string(), output.to_string(), options.clone());
+        let mut moved_bar = std::mem::replace(bar, WrappedBar::new_empty());
+        let (result, moved_bar) = blocking(move || {
+            let result = SSHHandler::get_blocking(&input, &output, &mut moved_bar, sink, &options);
+            (result, moved_bar)
+        })
+        .await;
+        *bar = moved_bar;
+        result
+    }
+
+    fn get_blocking(
+        input: &str,
+        output: &str,
+        bar: &mut WrappedBar,
+        sink: Option<ChannelWriter>,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        let timeouts = &options.timeouts;
+        let (session, remote_file) = SSHHandler::setup_session(input, bar.silent, options)?;
 
         let (channel, stat) = session
             .scp_recv(Path::new(&remote_file))
             .unwrap_or_else(|_| panic!("Remote file does not exist: {input}"));
-
-        let mut target =
-            File::create(output).unwrap_or_else(|_| panic!("Cannot create output file: {output}"));
+        let (target, transferred) = open_output(input, output, bar.silent, options, sink);
+        let mut target = target.into_write();
         bar.set_length(stat.size());
+        bar.set_position(transferred);
+        if transferred >= stat.size() && transferred > 0 {
+            return Ok(());
+        }
 
-        std::io::copy(
-            &mut bar.output.as_ref().unwrap().wrap_read(channel),
+        let mut channel = match transferred {
+            0 => channel,
+            _ => {
+                drop(channel);
+                SSHHandler::exec(
+                    &session,
+                    &format!("tail -c +{} -- {}", transferred + 1, quote(&remote_file)),
+                )
+            }
+        };
+        let result = std::io::copy(
+            &mut bar.wrap_read(GuardedReader::new(&mut channel, timeouts)),
             &mut target,
-        )
-        .expect("Cannot write contents to file");
+        );
+        SSHHandler::check_copy(result.and_then(|copied| target.flush().map(|_| copied)))?;
+        if transferred > 0 {
+            SSHHandler::check_exit(channel);
+        }
         Ok(())
     }
 
-    pub async fn put(input: &str, output: &str, mut bar: WrappedBar) -> Result<(), ValidateError> {
-        let (session, remote_file) = SSHHandler::setup_session(output, bar.silent);
-        let input_file = File::open(input).expect("Cannot open input file for SSH read");
-        let total_size = input_file
-            .metadata()
-            .expect("Cannot determine input file size for HTTPS read")
-            .len();
-
-        let mut channel = session
-            .scp_send(Path::new(&remote_file), 0o777, total_size, None)
-            .unwrap_or_else(|_| panic!("Cannot create SSH channel"));
+    pub async fn put(
+        input: &str,
+        output: &str,
+        bar: WrappedBar,
+        source: Option<Input>,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        let input = open_input(input, source)
+            .await
+            .expect("Cannot open input for SSH read");
+        let is_resumable = input.is_resumable();
+        let (output, options) = (output.to_string(), options.clone());
+        blocking(move || SSHHandler::put_blocking(input, is_resumable, &output, bar, &options))
+            .await
+    }
 
+    fn put_blocking(
+        input: Input,
+        is_resumable: bool,
+        output: &str,
+        mut bar: WrappedBar,
+        options: &Options,
+    ) -> Result<(), ValidateError> {
+        let timeouts = &options.timeouts;
+        let (session, remote_file) = SSHHandler::setup_session(output, bar.silent, options)?;
+        if options.create_dirs {
+            SSHHandler::create_dirs(&session, &remote_file);
+        }
+        let total_size = input.size.unwrap_or_else(|| {
+            panic!("Cannot upload a stream of unknown size via scp, use sftp:// instead.")
+        });
+        let (mode, times) = match input.metadata.filter(|_| options.preserve) {
+            Some(metadata) => (get_mode(&metadata) as i32, SSHHandler::times(&metadata)),
+            None => (DEFAULT_MODE, None),
+        };
+        let mut reader = input.reader;
+        let handle = Handle::current();
+        let transferred = match is_resumable && options.upload_resume == "append" {
+            true => SSHHandler::remote_size(&session, &remote_file)
+                .filter(|&size| 0 < size && size <= total_size)
+                .filter(|&size| {
+                    let local = handle.block_on(SSHHandler::prefix_sha256(&mut reader, size));
+                    local.ok() == SSHHandler::remote_sha256(&session, &remote_file, size)
+                })
+                .unwrap_or(0),
+            false => 0,
+        };
         bar.set_length(total_size);
+        bar.set_position(transferred);
+        if transferred == total_size && transferred > 0 {
+            return Ok(());
+        }
+        handle
+            .block_on(reader.skip(transferred))
+            .expect("Cannot seek in input file");
+        let input_file = handle.block_on(reader.into_std());
+
+        let mut channel = match transferred {
+            0 => session
+                .scp_send(Path::new(&remote_file), mode, total_size, times)
+                .unwrap_or_else(|_| panic!("Cannot create SSH channel")),
+            _ => SSHHandler::exec(&session, &format!("cat >> {}", quote(&remote_file))),
+        };
 
-        std::io::copy(
-            &mut bar.output.as_ref().unwrap().wrap_read(input_file),
+        let result = std::io::copy(
+            &mut bar.wrap_read(GuardedReader::new(input_file, timeouts)),
             &mut channel,
-        )
-        .expect("Cannot write contents to file");
+        );
+        SSHHandler::check_copy(result)?;
+        if transferred > 0 {
+            channel.send_eof().expect("Cannot finish SSH upload");
+            SSHHandler::check_exit(channel);
+            if let Some((mtime, atime)) = times {
+                let stat = ssh2::FileStat {
+                    size: None,
+                    uid: None,
+                    gid: None,
+                    perm: Some(mode as u32),
+                    atime: Some(atime),
+                    mtime: Some(mtime),
+                };
+                let _ = session
+                    .sftp()
+                    .and_then(|sftp| sftp.setstat(Path::new(&remote_file), stat));
+            }
+        }
         Ok(())
     }
 
-    fn setup_session(address: &str, silent: bool) -> (Session, String) {
-        let parsed_address = ParsedAddress::parse_address(address, silent);
-        let tcp =
-            TcpStream::connect(&parsed_address.server).expect("Cannot connect to SSH address");
+    pub async fn stat(
+        input: &str,
+        options: &Options,
+    ) -> Result<Option<RemoteMetadata>, ValidateError> {
+        let (input, options) = (input.to_string(), options.clone());
+        blocking(move || SSHHandler::stat_blocking(&input, &options)).await
+    }
+
+    fn stat_blocking(
+        input: &str,
+        options: &Options,
+    ) -> Result<Option<RemoteMetadata>, ValidateError> {
+        let (session, remote_file) = SSHHandler::setup_session(input, true, options)?;
+        let modified = session
+            .sftp()
+            .and_then(|sftp| sftp.stat(Path::new(&remote_file)))
+            .ok()
+            .and_then(|stat| stat.mtime)
+            .map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime));
+        Ok(session
+            .scp_recv(Path::new(&remote_file))
+            .ok()
+            .map(|(_, stat)| RemoteMetadata {
+                size: Some(stat.size()),
+                modified,
+                permissions: Some(stat.mode() as u32 & 0o7777),
+                ..Default::default()
+            }))
+    }
+
+    fn times(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+        let seconds = |time: std::io::Result<SystemTime>| {
+            time.ok()?
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs())
+        };
+        Some((seconds(metadata.modified())?, seconds(metadata.accessed())?))
+    }
+
+    fn exec(session: &Session, command: &str) -> Channel {
+        let mut channel = session
+            .channel_session()
+            .expect("Cannot create SSH channel");
+        channel
+            .exec(command)
+            .unwrap_or_else(|e| panic!("Cannot run remote command {command}: {e}"));
+        channel
+    }
+
+    fn create_dirs(session: &Session, remote_file: &str) {
+        let Some(parent) = Path::new(remote_file)
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        else {
+            return;
+        };
+        let command = format!("mkdir -p -- {}", quote(&parent.to_string_lossy()));
+        SSHHandler::check_exit(SSHHandler::exec(session, &command));
+    }
+
+    fn remote_size(session: &Session, remote_file: &str) -> Option<u64> {
+        let command = format!("wc -c < {}", quote(remote_file));
+        SSHHandler::remote_output(session, &command)?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    fn remote_sha256(session: &Session, remote_file: &str, size: u64) -> Option<String> {
+        let command = format!("head -c {size} -- {} | sha256sum", quote(remote_file));
+        let output = SSHHandler::remote_output(session, &command)?;
+        output.split_whitespace().next().map(str::to_string)
+    }
+
+    fn remote_output(session: &Session, command: &str) -> Option<String> {
+        let mut channel = SSHHandler::exec(session, command);
+        let mut output = String::new();
+        channel.read_to_string(&mut output).ok()?;
+        channel.wait_close().ok()?;
+        match channel.exit_status() {
+            Ok(0) => Some(output),
+            _ => None,
+        }
+    }
+
+    async fn prefix_sha256(reader: &mut InputReader, size: u64) -> std::io::Result<String> {
+        let mut hasher = Sha256::new();
+        let mut prefix = reader.take(size);
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            match prefix.read(&mut buffer).await? {
+                0 => break,
+                n => hasher.update(&buffer[..n]),
+            }
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    fn check_exit(mut channel: Channel) {
+        channel.wait_close().expect("Cannot close SSH channel");
+        match channel.exit_status() {
+            Ok(0) => {}
+            status => panic!("Remote SSH command failed: {status:?}"),
+        }
+    }
+
+    fn check_copy(result: std::io::Result<u64>) -> Result<(), ValidateError> {
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => match timeout_from_io(&e) {
+                Some(timeout) => Err(timeout.into()),
+                None => panic!("Cannot write contents to file: {e}"),
+            },
+        }
+    }
+
+    fn setup_session(
+        address: &str,
+        silent: bool,
+        options: &Options,
+    ) -> Result<(Session, String), ValidateError> {
+        let mut parsed_address = ParsedAddress::parse_address(address, silent);
+        let host = SshHost::resolve(&mut parsed_address, address);
+        let dial = dial_address(&host, &parsed_address, options, silent)?;
+        let session = SSHHandler::session(&dial, &parsed_address, &host, options, silent)?;
+        let remote_file = String::from("/")
+            + &parsed_address.path_segments.join("/")[..]
+            + "/"
+            + &parsed_address.file[..];
+        Ok((session, remote_file))
+    }
+
+    pub fn session(
+        dial: &str,
+        parsed_address: &ParsedAddress,
+        host: &SshHost,
+        options: &Options,
+        silent: bool,
+    ) -> Result<Session, ValidateError> {
+        let timeouts = &options.timeouts;
+        let tcp = match timeouts.connect_std(dial) {
+            Ok(tcp) => tcp,
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                return Err(TimeoutError::Connect.into())
+            }
+            Err(e) => panic!("Cannot connect to SSH address: {e}"),
+        };
         let mut session = Session::new().unwrap();
 
         session.set_tcp_stream(tcp);
-        session.handshake().expect("SSH handshake failed");
+        session.set_timeout(SSHHandler::millis(timeouts.connect));
+        if let Err(e) = session.handshake() {
+            match e.code() {
+                ssh2::ErrorCode::Session(LIBSSH2_ERROR_TIMEOUT) => {
+                    return Err(TimeoutError::Connect.into())
+                }
+                _ => panic!("SSH handshake failed: {e}"),
+            }
+        }
+        verify_host_key::<QuestionWrapped>(
+            session.known_hosts().expect("Cannot read SSH known hosts"),
+            &known_hosts_path(),
+            session.host_key(),
+            &parsed_address.server,
+            &options.host_key_checking,
+            silent,
+        )?;
+        session.set_timeout(SSHHandler::millis(timeouts.read));
+        let username = &parsed_address.username;
         if parsed_address.password != "anonymous" {
-            session
-                .userauth_password(&parsed_address.username, &parsed_address.password)
-                .expect("SSH Authentication failed");
+            let _ = session.userauth_password(username, &parsed_address.password);
         } else {
-            let ssh_keys = get_possible_ssh_keys_path(silent);
-            let mut is_ok = false;
-            for ssh_key in ssh_keys.iter() {
-                if session
-                    .userauth_pubkey_file(
-                        &parsed_address.username,
-                        Some(Path::new(&(ssh_key.to_owned() + ".pub"))),
-                        Path::new(ssh_key),
-                        None,
-                    )
-                    .is_ok()
-                {
-                    is_ok = true;
+            if has_agent() {
+                let _ = session.userauth_agent(username);
+            }
+            for identity in identity_files(&options.identity, &host.identity_files, silent) {
+                if session.authenticated() {
                     break;
                 }
+                let _ = session.userauth_pubkey_file(
+                    username,
+                    public_key_file(&identity).as_deref(),
+                    &identity,
+                    passphrase::<QuestionWrapped>(&identity).as_deref(),
+                );
             }
-
-            if !is_ok {
-                println!("SSH Authentication failed. No password specified. Is passwordless authentication set up?");
+            if !session.authenticated() {
+                let mut prompt = InteractivePrompt::<QuestionWrapped>::default();
+                let _ = session.userauth_keyboard_interactive(username, &mut prompt);
+            }
+            if !session.authenticated() {
+                if let Some(password) =
+                    ask_password::<QuestionWrapped>(username, &parsed_address.server)
+                {
+                    let _ = session.userauth_password(username, &password);
+                }
             }
         }
-        let remote_file = String::from("/")
-            + &parsed_address.path_segments.join("/")[..]
-            + "/"
-            + &parsed_address.file[..];
-        (session, remote_file)
+        match session.authenticated() {
+            true => Ok(session),
+            false => Err(ValidateError::Unauthorized {
+                host: format!("{username}@{}", parsed_address.server),
+            }),
+        }
+    }
+
+    fn millis(duration: Option<Duration>) -> u32 {
+        duration.map_or(0, |d| d.as_millis().try_into().unwrap_or(u32::MAX))
     }
 
     pub async fn get_links(_input: String) -> Result<Vec<String>, Error> {
@@ -115,3 +424,42 @@ impl SSHHandler {
 async fn test_should_panic_when_not_implemented() {
     let _ = SSHHandler::get_links("dummy".to_string()).await;
 }
+
+#[test]
+fn test_quote_works_when_single_quote() {
+    assert_eq!(quote("/tmp/it's here"), "'/tmp/it'\\''s here'");
+}
+
+#[tokio::test]
+async fn test_stat_does_not_block_runtime_when_server_is_silent() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("ssh://{}/file", listener.local_addr().unwrap());
+    let _handle = std::thread::spawn(move || {
+        let _socket = listener.accept().unwrap();
+        std::thread::sleep(Duration::from_secs(2));
+    });
+    let options = Options {
+        timeouts: crate::timeout::Timeouts {
+            connect: Some(Duration::from_millis(500)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let is_runtime_free = tokio::select! {
+        _ = SSHHandler::stat(&address, &options) => false,
+        _ = tokio::time::sleep(Duration::from_millis(50)) => true,
+    };
+
+    assert!(is_runtime_free);
+}
+
+#[tokio::test]
+async fn test_prefix_sha256_works_when_typical() {
+    let mut reader = open_input("LICENCE.md", None).await.unwrap().reader;
+
+    let actual = SSHHandler::prefix_sha256(&mut reader, 4).await.unwrap();
+
+    let expected = Sha256::digest(&std::fs::read("LICENCE.md").unwrap()[..4]);
+    assert_eq!(actual, format!("{expected:x}"));
+}
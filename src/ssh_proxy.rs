@@ -0,0 +1,237 @@
+use ssh2::Session;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use crate::address::ParsedAddress;
+use crate::driver::Options;
+use crate::error::ValidateError;
+use crate::ssh::SSHHandler;
+use crate::ssh_config::SshHost;
+
+const BUFFER_SIZE: usize = 32 * 1024;
+const IDLE_MILLISECONDS: u64 = 1;
+
+/// Returns the address to open the TCP connection to: the server itself, or a
+/// local port forwarded through `ProxyCommand` or the `ProxyJump` hosts.
+pub fn dial_address(
+    host: &SshHost,
+    parsed_address: &ParsedAddress,
+    options: &Options,
+    silent: bool,
+) -> Result<String, ValidateError> {
+    if let Some(command) = host.proxy_command.as_ref() {
+        return forward_command(command, parsed_address).map_err(|e| ValidateError::Proxy {
+            server: parsed_address.server.clone(),
+            reason: format!("cannot run ProxyCommand {command}: {e}"),
+        });
+    }
+    let Some(jumps) = host.proxy_jump.as_ref() else {
+        return Ok(parsed_address.server.clone());
+    };
+
+    let mut previous: Option<Session> = None;
+    for jump in jumps.split(',') {
+        let jump_address = format!("ssh://{}/", jump.trim());
+        let mut jump_parsed = ParsedAddress::parse_address(&jump_address, silent);
+        let jump_host = SshHost::resolve(&mut jump_parsed, &jump_address);
+        let dial = match previous.take() {
+            Some(session) => forward_channel(session, &jump_parsed.server)?,
+            None => jump_parsed.server.clone(),
+        };
+        if !silent {
+            println!("🔀 Jumping through {}", jump_parsed.server);
+        }
+        previous = Some(SSHHandler::session(
+            &dial,
+            &jump_parsed,
+            &jump_host,
+            options,
+            silent,
+        )?);
+    }
+    let session = previous.ok_or_else(|| ValidateError::Proxy {
+        server: parsed_address.server.clone(),
+        reason: "ProxyJump names no host".to_string(),
+    })?;
+    forward_channel(session, &parsed_address.server)
+}
+
+/// Opens a `direct-tcpip` channel to `target` and serves it on a local port.
+///
+/// An ssh session must not be used from two threads at once, so a single
+/// thread pumps both directions in non-blocking mode. When neither side has
+/// data it sleeps for `IDLE_MILLISECONDS`: idle tunnels wake up about a
+/// thousand times per second in exchange for low latency on busy ones.
+fn forward_channel(session: Session, target: &str) -> Result<String, ValidateError> {
+    let error = |reason: String| ValidateError::Proxy {
+        server: target.to_string(),
+        reason,
+    };
+    let (host, port) = target
+        .rsplit_once(':')
+        .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+        .ok_or_else(|| error("expected host:port".to_string()))?;
+    let mut channel = session
+        .channel_direct_tcpip(host, port, None)
+        .map_err(|e| error(format!("cannot open ssh tunnel: {e}")))?;
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(|e| error(e.to_string()))?;
+    let address = listener
+        .local_addr()
+        .map_err(|e| error(e.to_string()))?
+        .to_string();
+
+    thread::spawn(move || {
+        let Ok((mut local, _)) = listener.accept() else {
+            return;
+        };
+        session.set_blocking(false);
+        if local.set_nonblocking(true).is_err() {
+            return;
+        }
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        loop {
+            let mut is_idle = true;
+            match local.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    is_idle = false;
+                    if write_all(&mut channel, &buffer[..n]).is_err() {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => break,
+            }
+            match channel.read(&mut buffer) {
+                Ok(0) if channel.eof() => break,
+                Ok(0) => {}
+                Ok(n) => {
+                    is_idle = false;
+                    if write_all(&mut local, &buffer[..n]).is_err() {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => break,
+            }
+            if is_idle {
+                thread::sleep(Duration::from_millis(IDLE_MILLISECONDS));
+            }
+        }
+        let _ = channel.close();
+    });
+    Ok(address)
+}
+
+fn forward_command(command: &str, parsed_address: &ParsedAddress) -> io::Result<String> {
+    let (host, port) = parsed_address
+        .server
+        .rsplit_once(':')
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "expected host:port"))?;
+    let command = command
+        .replace("%h", host)
+        .replace("%p", port)
+        .replace("%r", &parsed_address.username)
+        .replace("%%", "%");
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = Command::new(shell)
+        .args([flag, &command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?.to_string();
+
+    thread::spawn(move || {
+        let Ok((local, _)) = listener.accept() else {
+            let _ = child.kill();
+            return;
+        };
+        let Ok(mut reader) = local.try_clone() else {
+            let _ = child.kill();
+            return;
+        };
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+        let mut writer: TcpStream = local;
+        let upstream = thread::spawn(move || io::copy(&mut reader, &mut stdin));
+        let _ = io::copy(&mut stdout, &mut writer);
+        let _ = writer.shutdown(std::net::Shutdown::Both);
+        let _ = upstream.join();
+        let _ = child.kill();
+        let _ = child.wait();
+    });
+    Ok(address)
+}
+
+fn write_all<W: Write>(writer: &mut W, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        match writer.write(data) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => data = &data[n..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(IDLE_MILLISECONDS))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_forward_command_works_when_typical() {
+    let parsed_address = ParsedAddress {
+        server: "example.com:22".to_string(),
+        username: "user".to_string(),
+        password: "anonymous".to_string(),
+        path_segments: vec![],
+        file: "".to_string(),
+    };
+    let address = forward_command("printf '%r@%h:%p' && cat", &parsed_address).unwrap();
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(b"!").unwrap();
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    let mut actual = String::new();
+    stream.read_to_string(&mut actual).unwrap();
+
+    assert_eq!(actual, "user@example.com:22!");
+}
+
+#[test]
+fn test_write_all_works_when_would_block() {
+    struct Flaky(Vec<u8>, bool);
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1 = !self.1;
+            if self.1 {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            self.0.push(buf[0]);
+            Ok(1)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    let mut writer = Flaky(vec![], false);
+
+    write_all(&mut writer, b"abc").unwrap();
+
+    assert_eq!(writer.0, b"abc");
+}
+
+#[test]
+fn test_forward_channel_fails_when_target_has_no_port() {
+    let actual = forward_channel(Session::new().unwrap(), "example.com");
+
+    assert!(matches!(actual, Err(ValidateError::Proxy { .. })));
+}
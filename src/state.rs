@@ -0,0 +1,74 @@
+use dotenvy::dotenv;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+
+use untildify::untildify;
+
+const DEFAULT_AIM_STATE_DIR: &str = "~/.cache/aim";
+
+pub struct State;
+impl State {
+    pub fn load(kind: &str, key: &str) -> Option<HashMap<String, String>> {
+        let contents = std::fs::read_to_string(State::path(kind, key)).ok()?;
+        Some(
+            contents
+                .lines()
+                .filter_map(|line| line.split_once('='))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    pub fn save(kind: &str, key: &str, values: &HashMap<String, String>) {
+        let path = State::path(kind, key);
+        std::fs::create_dir_all(path.parent().unwrap()).expect("Cannot create aim state folder");
+        let mut contents = String::new();
+        for (k, v) in values {
+            contents += &format!("{k}={v}\n");
+        }
+        std::fs::write(path, contents).expect("Cannot write aim state");
+    }
+
+    pub fn remove(kind: &str, key: &str) {
+        let _ = std::fs::remove_file(State::path(kind, key));
+    }
+
+    pub fn dir() -> PathBuf {
+        dotenv().ok();
+        let dir = env::var("AIM_STATE_DIR").unwrap_or_else(|_| {
+            env::var("XDG_CACHE_HOME")
+                .map(|cache| cache + "/aim")
+                .unwrap_or_else(|_| DEFAULT_AIM_STATE_DIR.to_string())
+        });
+        PathBuf::from(untildify(&dir))
+    }
+
+    fn path(kind: &str, key: &str) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(key.as_bytes());
+        State::dir()
+            .join(kind)
+            .join(format!("{:x}", hasher.finalize()))
+    }
+}
+
+#[test]
+fn test_state_save_load_works_when_typical() {
+    let key = "test_state_save_load_works_when_typical";
+    let values = HashMap::from([
+        (
+            "url".to_string(),
+            "http://127.0.0.1/files/1?a=b".to_string(),
+        ),
+        ("size".to_string(), "42".to_string()),
+    ]);
+
+    State::save("test", key, &values);
+    let actual = State::load("test", key);
+    State::remove("test", key);
+
+    assert_eq!(actual, Some(values));
+    assert_eq!(State::load("test", key), None);
+}
@@ -0,0 +1,272 @@
+use std::future::Future;
+use std::io::Read;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::error::{TimeoutError, ValidateError};
+
+const DEFAULT_SPEED_LIMIT: u64 = 1;
+const DEFAULT_SPEED_TIME: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    pub read: Option<Duration>,
+    pub total: Option<Duration>,
+    pub speed_limit: Option<u64>,
+    pub speed_time: Option<Duration>,
+}
+
+impl Timeouts {
+    pub async fn connect<F: Future>(&self, future: F) -> Result<F::Output, TimeoutError> {
+        match self.connect {
+            Some(duration) => tokio::time::timeout(duration, future)
+                .await
+                .map_err(|_| TimeoutError::Connect),
+            None => Ok(future.await),
+        }
+    }
+
+    pub async fn read<F: Future>(&self, future: F) -> Result<F::Output, TimeoutError> {
+        match self.stall() {
+            Some((duration, error)) => tokio::time::timeout(duration, future)
+                .await
+                .map_err(|_| error),
+            None => Ok(future.await),
+        }
+    }
+
+    pub async fn total<F: Future>(&self, future: F) -> Result<F::Output, TimeoutError> {
+        match self.total {
+            Some(duration) => tokio::time::timeout(duration, future)
+                .await
+                .map_err(|_| TimeoutError::Total),
+            None => Ok(future.await),
+        }
+    }
+
+    pub fn connect_std(&self, address: &str) -> std::io::Result<TcpStream> {
+        match self.connect {
+            Some(duration) => {
+                let mut last_error = std::io::Error::other(format!("Cannot resolve {address}"));
+                for address in address.to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&address, duration) {
+                        Ok(stream) => return Ok(stream),
+                        Err(e) => last_error = e,
+                    }
+                }
+                Err(last_error)
+            }
+            None => TcpStream::connect(address),
+        }
+    }
+
+    pub fn speed(&self) -> Option<(u64, Duration)> {
+        match (self.speed_limit, self.speed_time) {
+            (None, None) => None,
+            (limit, time) => Some((
+                limit.unwrap_or(DEFAULT_SPEED_LIMIT),
+                time.unwrap_or(DEFAULT_SPEED_TIME),
+            )),
+        }
+    }
+
+    fn stall(&self) -> Option<(Duration, TimeoutError)> {
+        let speed_time = self.speed().map(|(_, time)| time);
+        match (self.read, speed_time) {
+            (Some(read), Some(time)) if time < read => Some((time, self.low_speed_error())),
+            (Some(read), _) => Some((read, TimeoutError::Read)),
+            (None, Some(time)) => Some((time, self.low_speed_error())),
+            (None, None) => None,
+        }
+    }
+
+    fn low_speed_error(&self) -> TimeoutError {
+        let (limit, time) = self.speed().unwrap();
+        TimeoutError::LowSpeed {
+            limit,
+            time: time.as_secs(),
+        }
+    }
+}
+
+pub struct SpeedGuard {
+    speed: Option<(u64, Duration)>,
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+impl SpeedGuard {
+    pub fn new(timeouts: &Timeouts) -> Self {
+        SpeedGuard {
+            speed: timeouts.speed(),
+            window_start: Instant::now(),
+            window_bytes: 0,
+        }
+    }
+
+    pub fn update(&mut self, bytes: u64) -> Result<(), TimeoutError> {
+        let Some((limit, time)) = self.speed else {
+            return Ok(());
+        };
+        self.window_bytes += bytes;
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= time {
+            if (self.window_bytes as f64) / elapsed.as_secs_f64() < limit as f64 {
+                return Err(TimeoutError::LowSpeed {
+                    limit,
+                    time: time.as_secs(),
+                });
+            }
+            self.window_start = Instant::now();
+            self.window_bytes = 0;
+        }
+        Ok(())
+    }
+}
+
+pub struct GuardedReader<R: Read> {
+    inner: R,
+    guard: SpeedGuard,
+}
+
+impl<R: Read> GuardedReader<R> {
+    pub fn new(inner: R, timeouts: &Timeouts) -> Self {
+        GuardedReader {
+            inner,
+            guard: SpeedGuard::new(timeouts),
+        }
+    }
+}
+
+impl<R: Read> Read for GuardedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.guard.update(count as u64)?;
+        Ok(count)
+    }
+}
+
+pub fn timeout_from_io(error: &std::io::Error) -> Option<TimeoutError> {
+    if !matches!(
+        error.kind(),
+        std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+    ) {
+        return None;
+    }
+    let inner = error
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<ValidateError>());
+    Some(match inner {
+        Some(ValidateError::Timeout {
+            source: TimeoutError::LowSpeed { limit, time },
+        }) => TimeoutError::LowSpeed {
+            limit: *limit,
+            time: *time,
+        },
+        Some(ValidateError::Timeout {
+            source: TimeoutError::Connect,
+        }) => TimeoutError::Connect,
+        Some(ValidateError::Timeout {
+            source: TimeoutError::Total,
+        }) => TimeoutError::Total,
+        _ => TimeoutError::Read,
+    })
+}
+
+#[test]
+fn test_speed_is_none_when_not_configured() {
+    assert_eq!(Timeouts::default().speed(), None);
+}
+
+#[test]
+fn test_speed_uses_curl_defaults_when_partially_configured() {
+    let timeouts = Timeouts {
+        speed_limit: Some(1000),
+        ..Default::default()
+    };
+    assert_eq!(timeouts.speed(), Some((1000, DEFAULT_SPEED_TIME)));
+
+    let timeouts = Timeouts {
+        speed_time: Some(Duration::from_secs(5)),
+        ..Default::default()
+    };
+    assert_eq!(
+        timeouts.speed(),
+        Some((DEFAULT_SPEED_LIMIT, Duration::from_secs(5)))
+    );
+}
+
+#[test]
+fn test_speed_guard_fails_when_too_slow() {
+    let mut guard = SpeedGuard::new(&Timeouts {
+        speed_limit: Some(1_000_000),
+        speed_time: Some(Duration::from_millis(10)),
+        ..Default::default()
+    });
+    assert!(guard.update(1).is_ok());
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(matches!(
+        guard.update(1),
+        Err(TimeoutError::LowSpeed { .. })
+    ));
+}
+
+#[test]
+fn test_speed_guard_works_when_fast_enough() {
+    let mut guard = SpeedGuard::new(&Timeouts {
+        speed_limit: Some(1),
+        speed_time: Some(Duration::from_millis(10)),
+        ..Default::default()
+    });
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(guard.update(1_000_000).is_ok());
+}
+
+#[tokio::test]
+async fn test_read_fails_when_stalled() {
+    let timeouts = Timeouts {
+        read: Some(Duration::from_millis(10)),
+        ..Default::default()
+    };
+    let result = timeouts
+        .read(tokio::time::sleep(Duration::from_secs(10)))
+        .await;
+    assert!(matches!(result, Err(TimeoutError::Read)));
+}
+
+#[tokio::test]
+async fn test_connect_fails_when_black_holed() {
+    let timeouts = Timeouts {
+        connect: Some(Duration::from_millis(10)),
+        ..Default::default()
+    };
+    let result = timeouts
+        .connect(tokio::time::sleep(Duration::from_secs(10)))
+        .await;
+    assert!(matches!(result, Err(TimeoutError::Connect)));
+}
+
+#[tokio::test]
+async fn test_total_works_when_fast_enough() {
+    let timeouts = Timeouts {
+        total: Some(Duration::from_secs(10)),
+        ..Default::default()
+    };
+    assert!(timeouts.total(async { 42 }).await.is_ok());
+}
+
+#[test]
+fn test_timeout_from_io_keeps_low_speed() {
+    let error: std::io::Error = TimeoutError::LowSpeed { limit: 1, time: 2 }.into();
+    assert!(matches!(
+        timeout_from_io(&error),
+        Some(TimeoutError::LowSpeed { limit: 1, time: 2 })
+    ));
+}
+
+#[test]
+fn test_timeout_from_io_is_none_when_not_timeout() {
+    let error = std::io::Error::other("other");
+    assert!(timeout_from_io(&error).is_none());
+}
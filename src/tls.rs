@@ -1,22 +1,58 @@
 extern crate native_tls;
 
-use native_tls::TlsConnector;
-use std::net::TcpStream;
+use native_tls::{Certificate, TlsConnector, TlsConnectorBuilder};
+
+use crate::driver::Options;
+use crate::error::TimeoutError;
+use crate::timeout::Timeouts;
 
 pub trait TLSTrait {
-    fn has_tls(host: &str, port: &str) -> bool;
+    fn has_tls(host: &str, port: &str, timeouts: &Timeouts) -> Result<bool, TimeoutError>;
 }
 
 pub struct TLS;
 impl TLSTrait for TLS {
-    fn has_tls(host: &str, port: &str) -> bool {
+    fn has_tls(host: &str, port: &str, timeouts: &Timeouts) -> Result<bool, TimeoutError> {
         let connector = TlsConnector::new().unwrap();
-        let stream = TcpStream::connect(host.to_string() + ":" + port).unwrap();
+        let stream = match timeouts.connect_std(&(host.to_string() + ":" + port)) {
+            Ok(stream) => stream,
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                return Err(TimeoutError::Connect)
+            }
+            Err(e) => panic!("Cannot connect to {host}:{port}: {e}"),
+        };
+        stream.set_read_timeout(timeouts.connect).unwrap();
         let stream = connector.connect(host, stream).ok();
-        stream.is_some()
+        Ok(stream.is_some())
+    }
+}
+/// Reads the PEM bundle passed with `--cacert`, if any.
+pub fn ca_bundle(options: &Options) -> Option<Vec<u8>> {
+    if options.cacert.is_empty() {
+        return None;
     }
+    Some(
+        std::fs::read(&options.cacert)
+            .unwrap_or_else(|e| panic!("Cannot read CA certificates {}: {e}", options.cacert)),
+    )
 }
+
+pub fn connector_builder(options: &Options) -> TlsConnectorBuilder {
+    let mut builder = TlsConnector::builder();
+    if let Some(pem) = ca_bundle(options) {
+        let certificates = Certificate::stack_from_pem(&pem)
+            .unwrap_or_else(|e| panic!("Cannot parse CA certificates {}: {e}", options.cacert));
+        for certificate in certificates {
+            builder.add_root_certificate(certificate);
+        }
+    }
+    builder
+        .danger_accept_invalid_certs(options.insecure)
+        .danger_accept_invalid_hostnames(options.insecure);
+    builder
+}
+
 #[test]
 fn test_has_tls_when_typical() {
-    assert!(TLS::has_tls("google.com", "443"));
+    assert!(TLS::has_tls("google.com", "443", &Timeouts::default()).unwrap());
 }
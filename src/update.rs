@@ -0,0 +1,183 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::driver::Options;
+use crate::install::Installer;
+use crate::release::{Asset, Release};
+use crate::state::State;
+
+const DEFAULT_UPDATE_SOURCE: &str = concat!("gh:mihaigalos/", env!("CARGO_PKG_NAME"));
+const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+const UPDATE_STATE: &str = "update";
+const BACKUP_KEY: &str = "backup";
+
+pub struct Updater {
+    pub source: String,
+    pub public_key: Option<String>,
+}
+
+impl Updater {
+    pub fn new(source: &str, public_key: &str) -> Updater {
+        let source = match source.is_empty() {
+            true => env::var("AIM_UPDATE_SOURCE").unwrap_or(DEFAULT_UPDATE_SOURCE.to_string()),
+            false => source.to_string(),
+        };
+        let public_key = match public_key.is_empty() {
+            true => env::var("AIM_UPDATE_PUBLIC_KEY").ok(),
+            false => Some(public_key.to_string()),
+        };
+        Updater { source, public_key }
+    }
+
+    pub async fn check(&self, options: &Options) -> io::Result<Option<String>> {
+        let asset = self.resolve("latest", options).await?;
+        let latest = Updater::normalize(&asset.version);
+        match latest == CURRENT_VERSION {
+            true => {
+                println!("aim {CURRENT_VERSION} is up to date.");
+                Ok(None)
+            }
+            false => {
+                println!("aim {latest} is available, {CURRENT_VERSION} is installed.");
+                Ok(Some(latest.to_string()))
+            }
+        }
+    }
+
+    pub async fn update(&self, version: &str, options: &Options) -> io::Result<()> {
+        let asset = self.resolve(version, options).await?;
+        let target_version = Updater::normalize(&asset.version).to_string();
+        if target_version == CURRENT_VERSION {
+            println!("aim {CURRENT_VERSION} is up to date.");
+            return Ok(());
+        }
+        if asset.sha256.is_none() && self.public_key.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Refusing to update: {} publishes no checksum for {}. Configure a public key to verify its signature.",
+                    self.source, asset.name
+                ),
+            ));
+        }
+
+        let work = env::temp_dir().join(format!("aim-update-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&work)?;
+        let result = self.replace(&work, &asset, options).await;
+        let _ = fs::remove_dir_all(&work);
+        result?;
+        println!("✅ Updated aim from {CURRENT_VERSION} to {target_version}.");
+        Ok(())
+    }
+
+    pub fn rollback() -> io::Result<()> {
+        let backup = State::load(UPDATE_STATE, BACKUP_KEY).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "No previous version to roll back to",
+            )
+        })?;
+        let (Some(path), Some(version)) = (backup.get("path"), backup.get("version")) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Corrupt update backup state",
+            ));
+        };
+        self_replace::self_replace(path)?;
+        State::remove(UPDATE_STATE, BACKUP_KEY);
+        println!("✅ Rolled back aim from {CURRENT_VERSION} to {version}.");
+        Ok(())
+    }
+
+    async fn resolve(&self, version: &str, options: &Options) -> io::Result<Asset> {
+        let shortcut = match version {
+            "" | "latest" => self.source.clone(),
+            version => format!("{}@{version}", self.source),
+        };
+        Release::resolve(&shortcut, options).await
+    }
+
+    async fn replace(&self, work: &Path, asset: &Asset, options: &Options) -> io::Result<()> {
+        let (download, _) =
+            Installer::download(work, &asset.url, &asset.name, asset.sha256.clone(), options)
+                .await?;
+        if let Some(public_key) = &self.public_key {
+            let signature_url = asset.signature.as_ref().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Refusing to update: no signature {}.sig published",
+                        asset.name
+                    ),
+                )
+            })?;
+            let signature = Release::get_text(signature_url, None, options).await?;
+            Updater::verify_signature(public_key, &fs::read(&download)?, &signature)?;
+        }
+        let extracted = Installer::unpack(work, &download, &asset.name)?;
+        let executable = Installer::find_executable(&extracted, env!("CARGO_PKG_NAME"))?;
+
+        let backup = State::dir()
+            .join(UPDATE_STATE)
+            .join(format!("{}-{CURRENT_VERSION}", env!("CARGO_PKG_NAME")));
+        fs::create_dir_all(backup.parent().unwrap())?;
+        fs::copy(env::current_exe()?, &backup)?;
+        State::save(
+            UPDATE_STATE,
+            BACKUP_KEY,
+            &HashMap::from([
+                ("path".to_string(), backup.to_string_lossy().to_string()),
+                ("version".to_string(), CURRENT_VERSION.to_string()),
+            ]),
+        );
+        self_replace::self_replace(executable)
+    }
+
+    pub fn verify_signature(public_key: &str, data: &[u8], signature: &str) -> io::Result<()> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let public_key = STANDARD
+            .decode(public_key.trim())
+            .map_err(|_| invalid("Invalid update public key, expected base64"))?;
+        let signature = STANDARD
+            .decode(signature.trim())
+            .map_err(|_| invalid("Invalid signature, expected base64"))?;
+        UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(data, &signature)
+            .map_err(|_| invalid("Signature verification failed"))
+    }
+
+    fn normalize(version: &str) -> &str {
+        version.trim_start_matches('v')
+    }
+}
+
+#[test]
+fn test_verify_signature_works_when_typical() {
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    let public_key = STANDARD.encode(key_pair.public_key().as_ref());
+    let signature = STANDARD.encode(key_pair.sign(b"aim").as_ref());
+
+    assert!(Updater::verify_signature(&public_key, b"aim", &signature).is_ok());
+    assert_eq!(
+        Updater::verify_signature(&public_key, b"tampered", &signature)
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_new_works_when_defaults() {
+    let updater = Updater::new("gl:mirror/aim", "");
+    assert_eq!(updater.source, "gl:mirror/aim");
+    assert!(DEFAULT_UPDATE_SOURCE.starts_with("gh:mihaigalos/"));
+}
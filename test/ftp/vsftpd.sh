@@ -6,6 +6,13 @@ echo "$FTP_USER:$FTP_PASS" | chpasswd
 mkdir -p /home/$FTP_USER/subfolder
 chmod -R 777 /home/$FTP_USER
 
+# Self-signed certificate for FTPS, shared with the tests through /src.
+openssl req -x509 -newkey rsa:2048 -nodes -days 1 \
+    -subj "/CN=localhost" \
+    -addext "subjectAltName=DNS:localhost,IP:127.0.0.1" \
+    -keyout /etc/vsftpd/vsftpd.key -out /etc/vsftpd/vsftpd.pem 2>/dev/null
+[ -d /src ] && cp /etc/vsftpd/vsftpd.pem /src/cert.pem
+
 cat <<EOF >> /etc/vsftpd/vsftpd.conf
 allow_writeable_chroot=YES
 anon_root=$ANON_ROOT
@@ -23,9 +30,17 @@ pasv_address=$PASV_ADDRESS
 pasv_enable=$PASV_ENABLE
 pasv_max_port=$PASV_MAX_PORT
 pasv_min_port=$PASV_MIN_PORT
+require_ssl_reuse=NO
+rsa_cert_file=/etc/vsftpd/vsftpd.pem
+rsa_private_key_file=/etc/vsftpd/vsftpd.key
 seccomp_sandbox=NO
+ssl_enable=YES
+force_local_data_ssl=NO
+force_local_logins_ssl=NO
 write_enable=YES
 xferlog_std_format=YES
 EOF
 
+# Explicit FTPS (AUTH TLS, optional) on 21, implicit FTPS on 990.
+/usr/sbin/vsftpd /etc/vsftpd/vsftpd.conf -olisten_port=990 -oimplicit_ssl=YES &
 /usr/sbin/vsftpd /etc/vsftpd/vsftpd.conf